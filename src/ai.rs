@@ -0,0 +1,187 @@
+// Built-in bot, which tries every placement of the active and held piece
+// and takes the one leaving the best board as judged by a weighted sum of
+// its features, in the spirit of https://tetris.wiki/Tetris_AI
+
+use crate::{
+    movegen::{placements, Placement},
+    scoring::{award, LockEvent, Spin},
+    state::State,
+};
+
+/// How much each feature of the board left by a placement counts towards
+/// choosing it. Positive weights are sought, negative ones avoided
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weights {
+    /// Per row of the tallest column
+    pub height: f64,
+    /// Per empty cell with a filled one somewhere above it
+    pub holes: f64,
+    /// Per cell of height difference between neighbouring columns
+    pub bumpiness: f64,
+    /// Per row of the deepest well, a column lower than both neighbours, up
+    /// to the 4 rows a tetris needs
+    pub well_depth: f64,
+    /// Per T-slot, an open cell with both corners below it filled and one
+    /// above it overhanging, that a T can spin into for a double
+    pub t_slots: f64,
+    /// Per line of attack the lock sends
+    pub attack: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            height: -0.5,
+            holes: -4.0,
+            bumpiness: -0.3,
+            well_depth: 1.0,
+            t_slots: 2.0,
+            attack: 2.0,
+        }
+    }
+}
+
+/// Well depth that counts towards `Weights::well_depth`
+const MAX_WELL: usize = 4;
+
+#[derive(Clone, Debug, Default)]
+pub struct Ai {
+    weights: Weights,
+}
+
+impl Ai {
+    pub fn new(weights: Weights) -> Self {
+        Ai { weights }
+    }
+
+    pub fn weights(&self) -> Weights {
+        self.weights
+    }
+
+    /// The best placement of the active piece or the one hold would swap
+    /// in, `None` if there's nowhere to put it
+    pub fn choose(&self, state: &mut State) -> Option<Placement> {
+        placements(state)
+            .into_iter()
+            .map(|p| (self.evaluate(state, &p), p))
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, p)| p)
+    }
+
+    /// Score of the board `placement` would leave, higher is better
+    pub fn evaluate(&self, state: &State, placement: &Placement) -> f64 {
+        let dims = state.dimensions();
+        let mut rows: Vec<Vec<bool>> = (0..dims.height + dims.buffer)
+            .map(|y| {
+                (0..dims.width)
+                    .map(|x| state.cell(x, y).is_some())
+                    .collect()
+            })
+            .collect();
+
+        let piece = placement.piece;
+        for (r, c) in state.rotation().coords(piece.typ(), piece.rot()) {
+            rows[piece.y() + r][(piece.x() + *c as i32) as usize] = true;
+        }
+        let before = rows.len();
+        rows.retain(|row| !row.iter().all(|c| *c));
+        let lines = (before - rows.len()) as u32;
+
+        let lock = LockEvent {
+            typ: piece.typ(),
+            lines,
+            spin: placement.spin,
+            perfect_clear: rows.iter().all(|row| row.iter().all(|c| !c)),
+            soft_drop_cells: 0,
+            hard_drop_cells: 0,
+        };
+        let combo = match (lines, state.scoring().combo()) {
+            (0, _) => 0,
+            (_, combo) => combo.map_or(0, |c| c + 1),
+        };
+        let attack = award(&lock, state.scoring().back_to_back(), combo, 1).attack;
+
+        let features = Features::new(&rows, dims.width);
+        let w = &self.weights;
+        w.height * features.height as f64
+            + w.holes * features.holes as f64
+            + w.bumpiness * features.bumpiness as f64
+            + w.well_depth * features.well_depth.min(MAX_WELL) as f64
+            + w.t_slots * features.t_slots as f64
+            + w.attack * attack as f64
+            // Wasting a spin is never worth it on its own
+            + if lines == 0 && placement.spin != Spin::None {
+                -w.attack.abs()
+            } else {
+                0.0
+            }
+    }
+}
+
+/// What the weights are applied to, for a board with rows cleared
+struct Features {
+    height: usize,
+    holes: usize,
+    bumpiness: usize,
+    well_depth: usize,
+    t_slots: usize,
+}
+
+impl Features {
+    /// `rows` is only the rows left after clearing, counted from the top
+    fn new(rows: &[Vec<bool>], width: usize) -> Self {
+        let filled = |x: i32, y: i32| {
+            x < 0
+                || x >= width as i32
+                || y >= rows.len() as i32
+                || (y >= 0 && rows[y as usize][x as usize])
+        };
+        let heights: Vec<usize> = (0..width)
+            .map(|x| {
+                rows.iter()
+                    .position(|row| row[x])
+                    .map_or(0, |top| rows.len() - top)
+            })
+            .collect();
+
+        let holes = (0..width)
+            .map(|x| {
+                let top = rows.len() - heights[x];
+                rows[top..].iter().filter(|row| !row[x]).count()
+            })
+            .sum();
+        let bumpiness = heights.windows(2).map(|h| h[0].abs_diff(h[1])).sum();
+        let well_depth = (0..width)
+            .map(|x| {
+                let left = if x == 0 { usize::MAX } else { heights[x - 1] };
+                let right = heights.get(x + 1).copied().unwrap_or(usize::MAX);
+                left.min(right).saturating_sub(heights[x])
+            })
+            .max()
+            .unwrap_or(0);
+
+        let mut t_slots = 0;
+        for y in 0..rows.len() as i32 {
+            for x in 0..width as i32 {
+                let open = [(x, y), (x - 1, y), (x + 1, y), (x, y + 1), (x, y - 1)]
+                    .iter()
+                    .all(|(x, y)| !filled(*x, *y));
+                if open
+                    && filled(x - 1, y + 1)
+                    && filled(x + 1, y + 1)
+                    && filled(x - 1, y - 1) != filled(x + 1, y - 1)
+                {
+                    t_slots += 1;
+                }
+            }
+        }
+
+        Features {
+            height: heights.iter().copied().max().unwrap_or(0),
+            holes,
+            bumpiness,
+            well_depth,
+            t_slots,
+        }
+    }
+}
@@ -1,149 +1,912 @@
-use std::fs;
+use std::{fs, time::Duration};
 
-use sdl2::keyboard::Keycode;
+#[cfg(feature = "tui")]
+use crossterm::event::KeyCode;
+#[cfg(feature = "sdl")]
+use sdl2::{
+    controller::{Axis, Button},
+    keyboard::Keycode,
+};
 use toml::{
     Table,
     Value::{self, Integer},
 };
 
-use crate::TetrErr;
+use crate::{
+    ai::Weights,
+    gravity::{Gravity, GravityMode},
+    input::{Action, Handling, Key},
+    randomizer::RandomizerKind,
+    rotations::{Kicks180, RotationSystemKind},
+    ruleset::Ruleset,
+    state::{Dimensions, HoldMode},
+    TetrErr,
+};
 
-#[derive(Clone, Copy)]
-pub struct KeyConfig {
-    left: Keycode,
-    right: Keycode,
-    sdrop: Keycode,
-    hdrop: Keycode,
-    hold: Keycode,
-    rrot: Keycode,
-    lrot: Keycode,
+/// Anything that can be bound to an action
+#[cfg(feature = "sdl")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Binding {
+    Key(Keycode),
+    Button(Button),
+    /// Axis pushed past the threshold, in the positive direction if `true`
+    Axis(Axis, bool),
 }
 
-impl KeyConfig {
-    pub fn left(&self) -> Keycode {
-        self.left
+#[cfg(feature = "sdl")]
+impl Binding {
+    /// Parse a binding from its config name, either an SDL key name like
+    /// `"left"`, a controller button like `"pad:dpleft"` or an axis
+    /// direction like `"axis:leftx-"`
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(button) = name.strip_prefix("pad:") {
+            Button::from_string(button).map(Binding::Button)
+        } else if let Some(axis) = name.strip_prefix("axis:") {
+            let (axis, positive) = match (axis.strip_suffix('+'), axis.strip_suffix('-')) {
+                (Some(a), _) => (a, true),
+                (_, Some(a)) => (a, false),
+                _ => return None,
+            };
+            Axis::from_string(axis).map(|a| Binding::Axis(a, positive))
+        } else {
+            Keycode::from_name(name).map(Binding::Key)
+        }
     }
 
-    pub fn right(&self) -> Keycode {
-        self.right
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(kc) => kc.name(),
+            Binding::Button(b) => format!("pad:{}", b.string()),
+            Binding::Axis(a, true) => format!("axis:{}+", a.string()),
+            Binding::Axis(a, false) => format!("axis:{}-", a.string()),
+        }
     }
+}
 
-    pub fn sdrop(&self) -> Keycode {
-        self.sdrop
-    }
+/// Bindings for every action, `B` being whatever the frontend reads input as
+#[derive(Clone)]
+pub struct KeyConfig<B> {
+    left: Vec<B>,
+    right: Vec<B>,
+    sdrop: Vec<B>,
+    hdrop: Vec<B>,
+    hold: Vec<B>,
+    rrot: Vec<B>,
+    lrot: Vec<B>,
+    rot180: Vec<B>,
+    pause: Vec<B>,
+    restart: Vec<B>,
+}
 
-    pub fn hdrop(&self) -> Keycode {
-        self.hdrop
-    }
+impl<B: Copy + PartialEq> KeyConfig<B> {
+    /// Parse the `[keys]` table. `parse` turns a name into a binding, or
+    /// `None` if the frontend can't use it, and `default` gives the bindings
+    /// of actions that end up with none
+    pub fn parse(
+        keys: &Table,
+        parse: impl Fn(&str, &str) -> Result<Option<B>, TetrErr>,
+        default: impl Fn(&str) -> Vec<B>,
+    ) -> Result<Self, TetrErr> {
+        let bindings = |action: &str| -> Result<Vec<B>, TetrErr> {
+            let key = format!("keys.{}", action);
+            let parsed = match keys.get(action) {
+                None => return Ok(default(action)),
+                Some(Value::String(s)) => parse(&key, s)?.into_iter().collect(),
+                // An empty list leaves the action unbound
+                Some(Value::Array(a)) if a.is_empty() => return Ok(Vec::new()),
+                Some(Value::Array(a)) => a
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| match v {
+                        Value::String(s) => parse(&format!("{}[{}]", key, i), s),
+                        _ => Err(malformed(&format!("{}[{}]", key, i), v, "a key name")),
+                    })
+                    .filter_map(Result::transpose)
+                    .collect::<Result<Vec<_>, TetrErr>>()?,
+                Some(v) => return Err(malformed(&key, v, "a key name or list of key names")),
+            };
+
+            // None of the names given are usable by this frontend
+            Ok(if parsed.is_empty() {
+                default(action)
+            } else {
+                parsed
+            })
+        };
 
-    pub fn hold(&self) -> Keycode {
-        self.hold
+        Ok(KeyConfig {
+            left: bindings("left")?,
+            right: bindings("right")?,
+            sdrop: bindings("sdrop")?,
+            hdrop: bindings("hdrop")?,
+            hold: bindings("hold")?,
+            rrot: bindings("rrot")?,
+            lrot: bindings("lrot")?,
+            rot180: bindings("rot180")?,
+            pause: bindings("pause")?,
+            restart: bindings("restart")?,
+        })
     }
 
-    pub fn rrot(&self) -> Keycode {
-        self.rrot
+    /// Everything bound to `action`
+    pub fn bindings(&self, action: Action) -> &[B] {
+        match action {
+            Action::Game(Key::Left) => &self.left,
+            Action::Game(Key::Right) => &self.right,
+            Action::Game(Key::SDrop) => &self.sdrop,
+            Action::Game(Key::HDrop) => &self.hdrop,
+            Action::Game(Key::Hold) => &self.hold,
+            Action::Game(Key::RRot) => &self.rrot,
+            Action::Game(Key::LRot) => &self.lrot,
+            Action::Game(Key::Rot180) => &self.rot180,
+            Action::Pause => &self.pause,
+            Action::Restart => &self.restart,
+        }
     }
 
-    pub fn lrot(&self) -> Keycode {
-        self.lrot
+    /// Find the action `binding` is bound to
+    pub fn action(&self, binding: B) -> Option<Action> {
+        [
+            (&self.left, Action::Game(Key::Left)),
+            (&self.right, Action::Game(Key::Right)),
+            (&self.sdrop, Action::Game(Key::SDrop)),
+            (&self.hdrop, Action::Game(Key::HDrop)),
+            (&self.hold, Action::Game(Key::Hold)),
+            (&self.rrot, Action::Game(Key::RRot)),
+            (&self.lrot, Action::Game(Key::LRot)),
+            (&self.rot180, Action::Game(Key::Rot180)),
+            (&self.pause, Action::Pause),
+            (&self.restart, Action::Restart),
+        ]
+        .into_iter()
+        .find(|(bindings, _)| bindings.contains(&binding))
+        .map(|(_, action)| action)
     }
 }
 
-#[derive(Clone, Copy)]
+/// What makes a bot's moves
+#[derive(Clone, Debug)]
+pub enum BotEngine {
+    /// An external program and its arguments, see `tbp`
+    Command(Vec<String>),
+    /// The built-in bot, see `ai`
+    Builtin(Weights),
+}
+
+/// A bot to play or give hints
+#[derive(Clone, Debug)]
+pub struct BotConfig {
+    pub engine: BotEngine,
+    /// Only show the bot's move instead of playing it
+    pub hint: bool,
+    /// Time between moves when the bot plays
+    pub delay: Duration,
+}
+
+#[derive(Clone)]
 pub struct Config {
-    dir_delay: i64,
-    keys: KeyConfig,
+    handling: Handling,
+    seed: Option<u64>,
+    randomizer: RandomizerKind,
+    gravity: Gravity,
+    kicks_180: Kicks180,
+    hold_mode: HoldMode,
+    rotation_system: Ruleset,
+    dimensions: Dimensions,
+    sprint_lines: Option<u32>,
+    previews: usize,
+    tick_rate: u32,
+    fps: Option<u32>,
+    finesse_trainer: bool,
+    bot: Option<BotConfig>,
+    keys: Table,
 }
 
 impl Config {
-    pub fn dir_delay(&self) -> i64 {
-        self.dir_delay
+    pub fn handling(&self) -> Handling {
+        self.handling
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn randomizer(&self) -> RandomizerKind {
+        self.randomizer
     }
 
-    pub fn keys(&self) -> &KeyConfig {
+    pub fn gravity(&self) -> Gravity {
+        self.gravity
+    }
+
+    pub fn kicks_180(&self) -> Kicks180 {
+        self.kicks_180
+    }
+
+    pub fn hold_mode(&self) -> HoldMode {
+        self.hold_mode
+    }
+
+    pub fn rotation_system(&self) -> &Ruleset {
+        &self.rotation_system
+    }
+
+    pub fn dimensions(&self) -> Dimensions {
+        self.dimensions
+    }
+
+    /// Line target when playing sprint mode, `None` for endless
+    pub fn sprint_lines(&self) -> Option<u32> {
+        self.sprint_lines
+    }
+
+    /// Number of next pieces shown
+    pub fn previews(&self) -> usize {
+        self.previews
+    }
+
+    /// The `[keys]` table, which each frontend parses into its own
+    /// `KeyConfig`
+    pub fn keys(&self) -> &Table {
         &self.keys
     }
+
+    /// Game updates per second
+    pub fn tick_rate(&self) -> u32 {
+        self.tick_rate
+    }
+
+    /// Cap on frames drawn per second, `None` to draw at the display's
+    /// refresh rate
+    pub fn fps(&self) -> Option<u32> {
+        self.fps
+    }
+
+    /// Show the right inputs after every finesse fault
+    pub fn finesse_trainer(&self) -> bool {
+        self.finesse_trainer
+    }
+
+    pub fn bot(&self) -> Option<&BotConfig> {
+        self.bot.as_ref()
+    }
 }
 
-pub fn get_config() -> Result<Config, TetrErr> {
-    let mut path = homedir::my_home()?.expect("Unable to find home dir");
+const TOP_LEVEL_KEYS: [&str; 24] = [
+    "das",
+    "dir_delay",
+    "arr",
+    "sdf",
+    "dcd",
+    "seed",
+    "randomizer",
+    "gravity",
+    "lock_delay",
+    "lock_resets",
+    "kicks_180",
+    "hold_mode",
+    "rotation_system",
+    "board_width",
+    "board_height",
+    "board_buffer",
+    "mode",
+    "sprint_lines",
+    "previews",
+    "tick_rate",
+    "fps",
+    "finesse",
+    "bot",
+    "keys",
+];
+
+const BOT_KEYS: [&str; 5] = ["command", "mode", "delay", "pps", "weights"];
+
+const WEIGHT_KEYS: [&str; 6] = [
+    "height",
+    "holes",
+    "bumpiness",
+    "well_depth",
+    "t_slots",
+    "attack",
+];
+
+const KEY_NAMES: [&str; 10] = [
+    "left", "right", "sdrop", "hdrop", "hold", "rrot", "lrot", "rot180", "pause", "restart",
+];
+
+#[cfg(feature = "sdl")]
+const BUTTON_NAMES: [&str; 21] = [
+    "a",
+    "b",
+    "x",
+    "y",
+    "back",
+    "guide",
+    "start",
+    "leftstick",
+    "rightstick",
+    "leftshoulder",
+    "rightshoulder",
+    "dpup",
+    "dpdown",
+    "dpleft",
+    "dpright",
+    "misc1",
+    "paddle1",
+    "paddle2",
+    "paddle3",
+    "paddle4",
+    "touchpad",
+];
+
+#[cfg(feature = "sdl")]
+const AXIS_NAMES: [&str; 6] = [
+    "leftx",
+    "lefty",
+    "rightx",
+    "righty",
+    "lefttrigger",
+    "righttrigger",
+];
+
+/// Raw contents of the config file, empty if there is none
+pub fn get_config_table() -> Result<Table, TetrErr> {
+    let mut path =
+        homedir::my_home()?.ok_or_else(|| TetrErr::Str("Unable to find home dir".to_string()))?;
     path.push(".config/tetrs/config.toml");
-    println!("{}", path.to_str().unwrap());
-    match fs::read_to_string(path) {
-        Ok(c) => {
-            println!("{}", c);
-            Ok(parse_map(c.parse().expect("Config is invalid toml")))
+    let mut tab: Table = match fs::read_to_string(&path) {
+        Ok(c) => c.parse()?,
+        Err(_) => return Ok(Table::new()),
+    };
+
+    // Inline ruleset files so the table (and any replay saved from it)
+    // doesn't depend on other files
+    if let Some(Value::String(s)) = tab.get("rotation_system") {
+        if RotationSystemKind::from_name(s).is_none() {
+            path.set_file_name(s);
+            let ruleset: Table = fs::read_to_string(&path)
+                .map_err(|e| format!("Unable to read ruleset {}: {}", path.display(), e))?
+                .parse()?;
+            tab.insert("rotation_system".to_string(), Value::Table(ruleset));
         }
-        Err(_) => Ok(parse_map(Table::new())),
     }
+
+    Ok(tab)
 }
 
-fn parse_map(tab: Table) -> Config {
-    println!("{:?}", tab);
-    let dir_delay = match tab.get("dir_delay") {
-        Some(Integer(d)) => *d,
-        None => 150,
-        _ => panic!("Malformed dir_delay value"),
+/// Warnings for any keys in the config that would be ignored
+pub fn config_warnings(tab: &Table) -> Vec<String> {
+    let mut warnings: Vec<String> = tab
+        .keys()
+        .filter(|k| !TOP_LEVEL_KEYS.contains(&k.as_str()))
+        .map(|k| format!("Unknown config key {}", k))
+        .collect();
+
+    if let Some(Value::Table(keys)) = tab.get("keys") {
+        warnings.extend(
+            keys.keys()
+                .filter(|k| !KEY_NAMES.contains(&k.as_str()))
+                .map(|k| format!("Unknown config key keys.{}", k)),
+        );
+    }
+
+    if let Some(Value::Table(bot)) = tab.get("bot") {
+        warnings.extend(
+            bot.keys()
+                .filter(|k| !BOT_KEYS.contains(&k.as_str()))
+                .map(|k| format!("Unknown config key bot.{}", k)),
+        );
+        if let Some(Value::Table(weights)) = bot.get("weights") {
+            warnings.extend(
+                weights
+                    .keys()
+                    .filter(|k| !WEIGHT_KEYS.contains(&k.as_str()))
+                    .map(|k| format!("Unknown config key bot.weights.{}", k)),
+            );
+        }
+    }
+
+    warnings
+}
+
+fn malformed(key: &str, value: &Value, expected: &str) -> TetrErr {
+    TetrErr::ConfigValue {
+        key: key.to_string(),
+        value: value.to_string(),
+        expected: expected.to_string(),
+    }
+}
+
+fn parse_u32(tab: &Table, key: &str, default: u32) -> Result<u32, TetrErr> {
+    match tab.get(key) {
+        Some(v @ Integer(i)) => {
+            u32::try_from(*i).map_err(|_| malformed(key, v, "a non-negative integer"))
+        }
+        None => Ok(default),
+        Some(v) => Err(malformed(key, v, "a non-negative integer")),
+    }
+}
+
+/// Like `parse_u32`, rejecting values below `min`
+fn parse_size(tab: &Table, key: &str, default: usize, min: u32) -> Result<usize, TetrErr> {
+    match parse_u32(tab, key, default as u32)? {
+        n if n < min => Err(malformed(
+            key,
+            &Integer(n as i64),
+            &format!("an integer of at least {}", min),
+        )),
+        n => Ok(n as usize),
+    }
+}
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            cur[j + 1] = (prev[j] + (ca != *cb) as usize)
+                .min(prev[j + 1] + 1)
+                .min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(any(feature = "sdl", feature = "tui"))]
+fn closest(name: &str, candidates: impl Iterator<Item = String>) -> Vec<String> {
+    let name = name.to_lowercase();
+    let mut names: Vec<(usize, String)> = candidates
+        .filter(|n| !n.is_empty())
+        .map(|n| (edit_distance(&name, &n.to_lowercase()), n))
+        .filter(|(d, n)| *d <= 2.max(n.len() / 3))
+        .collect();
+    names.sort();
+    names.dedup_by(|a, b| a.1 == b.1);
+    names.into_iter().take(3).map(|(_, n)| n).collect()
+}
+
+/// Binding names close to `name`, closest first
+#[cfg(feature = "sdl")]
+fn binding_suggestions(name: &str) -> Vec<String> {
+    if let Some(button) = name.strip_prefix("pad:") {
+        closest(button, BUTTON_NAMES.iter().map(|b| b.to_string()))
+            .into_iter()
+            .map(|b| format!("pad:{}", b))
+            .collect()
+    } else if let Some(axis) = name.strip_prefix("axis:") {
+        let sign = if axis.ends_with('-') { "-" } else { "+" };
+        let axis = axis.trim_end_matches(['+', '-']);
+        closest(axis, AXIS_NAMES.iter().map(|a| a.to_string()))
+            .into_iter()
+            .map(|a| format!("axis:{}{}", a, sign))
+            .collect()
+    } else {
+        // Keycodes are either characters or scancodes with bit 30 set
+        closest(
+            name,
+            (0..128)
+                .chain((0..512).map(|sc| sc | 1 << 30))
+                .filter_map(Keycode::from_i32)
+                .map(|kc| kc.name()),
+        )
+    }
+}
+
+/// Bindings for the SDL frontend, any unknown name is an error
+#[cfg(feature = "sdl")]
+pub fn sdl_keys(keys: &Table) -> Result<KeyConfig<Binding>, TetrErr> {
+    KeyConfig::parse(
+        keys,
+        |key, name| {
+            Binding::from_name(name)
+                .map(Some)
+                .ok_or_else(|| TetrErr::UnknownKey {
+                    key: key.to_string(),
+                    name: name.to_string(),
+                    suggestions: binding_suggestions(name),
+                })
+        },
+        |action| {
+            let kc = match action {
+                "left" => Keycode::Left,
+                "right" => Keycode::Right,
+                "sdrop" => Keycode::Down,
+                "hdrop" => Keycode::Up,
+                "lrot" => Keycode::Z,
+                "rrot" => Keycode::X,
+                "rot180" => Keycode::A,
+                "hold" => Keycode::LShift,
+                "pause" => Keycode::P,
+                _ => Keycode::Return,
+            };
+            vec![Binding::Key(kc)]
+        },
+    )
+}
+
+/// Terminal keys by their SDL names, the ones terminals can't report on their
+/// own (modifiers, keypad keys) aren't included
+#[cfg(feature = "tui")]
+const TERMINAL_KEYS: [(&str, KeyCode); 14] = [
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("return", KeyCode::Enter),
+    ("space", KeyCode::Char(' ')),
+    ("tab", KeyCode::Tab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+];
+
+/// SDL names of modifier keys, terminals only report them along with another
+/// key so they're valid names that can't be bound
+#[cfg(feature = "tui")]
+const MODIFIER_KEYS: [&str; 16] = [
+    "lshift",
+    "rshift",
+    "lctrl",
+    "rctrl",
+    "lalt",
+    "ralt",
+    "lgui",
+    "rgui",
+    "left shift",
+    "right shift",
+    "left ctrl",
+    "right ctrl",
+    "left alt",
+    "right alt",
+    "left gui",
+    "right gui",
+];
+
+/// Parse an SDL key name into the key a terminal reports for it
+#[cfg(feature = "tui")]
+pub fn terminal_key(name: &str) -> Option<KeyCode> {
+    let name = name.to_lowercase();
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_graphic() => return Some(KeyCode::Char(c)),
+        _ => (),
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+        return (1..=12).contains(&n).then_some(KeyCode::F(n));
+    }
+    TERMINAL_KEYS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, kc)| *kc)
+}
+
+/// SDL name of a terminal key, the inverse of `terminal_key`
+#[cfg(feature = "tui")]
+pub fn terminal_key_name(kc: KeyCode) -> String {
+    match kc {
+        KeyCode::Char(c) if c != ' ' => c.to_string(),
+        KeyCode::F(n) => format!("f{}", n),
+        _ => TERMINAL_KEYS
+            .iter()
+            .find(|(_, k)| *k == kc)
+            .map_or("?", |(n, _)| n)
+            .to_string(),
+    }
+}
+
+/// Whether `name` is a binding for SDL that a terminal can't read, modifiers,
+/// keypad keys and controller buttons or axes
+#[cfg(feature = "tui")]
+fn terminal_unsupported(name: &str) -> bool {
+    let name = name.to_lowercase();
+    ["pad:", "axis:", "keypad "]
+        .iter()
+        .any(|p| name.starts_with(p))
+        || MODIFIER_KEYS.contains(&&*name)
+}
+
+/// Bindings for the terminal frontend. Modifiers, keypad keys and controller
+/// bindings, which a terminal can't read, are skipped and an action left with no
+/// usable keys gets its default, any other unknown name is an error
+#[cfg(feature = "tui")]
+pub fn terminal_keys(keys: &Table) -> Result<KeyConfig<KeyCode>, TetrErr> {
+    KeyConfig::parse(
+        keys,
+        |key, name| match terminal_key(name) {
+            Some(kc) => Ok(Some(kc)),
+            None if terminal_unsupported(name) => Ok(None),
+            None => Err(TetrErr::UnknownKey {
+                key: key.to_string(),
+                name: name.to_string(),
+                suggestions: closest(
+                    name,
+                    TERMINAL_KEYS
+                        .iter()
+                        .map(|(n, _)| n)
+                        .chain(&MODIFIER_KEYS[..8])
+                        .map(|n| n.to_string())
+                        .chain((1..=12).map(|n| format!("f{}", n))),
+                ),
+            }),
+        },
+        |action| {
+            let kc = match action {
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "sdrop" => KeyCode::Down,
+                "hdrop" => KeyCode::Up,
+                "lrot" => KeyCode::Char('z'),
+                "rrot" => KeyCode::Char('x'),
+                "rot180" => KeyCode::Char('a'),
+                "hold" => KeyCode::Char('c'),
+                "pause" => KeyCode::Char('p'),
+                _ => KeyCode::Enter,
+            };
+            vec![kc]
+        },
+    )
+}
+
+fn parse_weights(tab: &Table) -> Result<Weights, TetrErr> {
+    let default = Weights::default();
+    let weight = |key: &str, default: f64| match tab.get(key) {
+        Some(Integer(w)) => Ok(*w as f64),
+        Some(Value::Float(w)) => Ok(*w),
+        None => Ok(default),
+        Some(v) => Err(malformed(&format!("bot.weights.{}", key), v, "a number")),
     };
-    
-    let keys = match tab.get("keys") {
-        Some(Value::Table(t)) => t,
-        None => &Table::new(),
-        _ => panic!("Malformed keys"),
+    Ok(Weights {
+        height: weight("height", default.height)?,
+        holes: weight("holes", default.holes)?,
+        bumpiness: weight("bumpiness", default.bumpiness)?,
+        well_depth: weight("well_depth", default.well_depth)?,
+        t_slots: weight("t_slots", default.t_slots)?,
+        attack: weight("attack", default.attack)?,
+    })
+}
+
+fn parse_bot(tab: &Table) -> Result<BotConfig, TetrErr> {
+    let command: Vec<String> = match tab.get("command") {
+        Some(Value::String(s)) => s.split_whitespace().map(str::to_string).collect(),
+        Some(v @ Value::Array(a)) => a
+            .iter()
+            .map(|arg| arg.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| malformed("bot.command", v, "a list of strings"))?,
+        None => Vec::new(),
+        Some(v) => return Err(malformed("bot.command", v, "a command")),
+    };
+    let weights = match tab.get("weights") {
+        Some(Value::Table(t)) => parse_weights(t)?,
+        None => Weights::default(),
+        Some(v) => return Err(malformed("bot.weights", v, "a table")),
+    };
+    // Without a command the built-in bot plays
+    let engine = match command.is_empty() {
+        true => BotEngine::Builtin(weights),
+        false => BotEngine::Command(command),
+    };
+
+    let hint = match tab.get("mode") {
+        Some(Value::String(s)) if s == "play" => false,
+        Some(Value::String(s)) if s == "hint" => true,
+        None => false,
+        Some(v) => return Err(malformed("bot.mode", v, "\"play\" or \"hint\"")),
     };
-    
-    let left = match keys.get("left") {
-        Some(Value::String(s)) => Keycode::from_name(s).expect("Unable to find keycode for left"),
-        None => Keycode::Left,
-        _ => panic!("Malformed left value"),
+
+    let delay = match (tab.get("delay"), tab.get("pps")) {
+        (Some(_), Some(_)) => {
+            return Err(TetrErr::Str(
+                "Only one of bot.delay and bot.pps can be set".to_string(),
+            ))
+        }
+        (Some(Integer(d)), None) if *d >= 0 => Duration::from_millis(*d as u64),
+        (Some(v), None) => return Err(malformed("bot.delay", v, "a non-negative integer")),
+        (None, Some(Integer(p))) if *p > 0 => Duration::from_secs_f64(1.0 / *p as f64),
+        (None, Some(Value::Float(p))) if *p > 0.0 => Duration::from_secs_f64(1.0 / *p),
+        (None, Some(v)) => return Err(malformed("bot.pps", v, "a positive number")),
+        (None, None) => Duration::from_millis(100),
+    };
+
+    Ok(BotConfig {
+        engine,
+        hint,
+        delay,
+    })
+}
+
+pub fn parse_map(tab: Table) -> Result<Config, TetrErr> {
+    let default = Handling::default();
+    // dir_delay is the old name for das
+    let das = match tab.get("das") {
+        Some(_) => parse_u32(&tab, "das", 0)?,
+        None => parse_u32(&tab, "dir_delay", default.das.as_millis() as u32)?,
+    };
+    let arr = parse_u32(&tab, "arr", default.arr.as_millis() as u32)?;
+    let dcd = parse_u32(&tab, "dcd", default.dcd.as_millis() as u32)?;
+
+    let sdf = match tab.get("sdf") {
+        Some(Value::String(s)) if s == "inf" => None,
+        Some(v @ Integer(f)) => match u32::try_from(*f) {
+            Ok(f) if f > 0 => Some(f),
+            _ => return Err(malformed("sdf", v, "a positive integer or \"inf\"")),
+        },
+        None => default.sdf,
+        Some(v) => return Err(malformed("sdf", v, "a positive integer or \"inf\"")),
     };
 
-    let right = match keys.get("right") {
-        Some(Value::String(s)) => Keycode::from_name(s).expect("Unable to find keycode for right"),
-        None => Keycode::Right,
-        _ => panic!("Malformed right value"),
+    let seed = match tab.get("seed") {
+        Some(v @ Integer(s)) => {
+            Some(u64::try_from(*s).map_err(|_| malformed("seed", v, "a non-negative integer"))?)
+        }
+        None => None,
+        Some(v) => return Err(malformed("seed", v, "a non-negative integer")),
     };
 
-    let sdrop = match keys.get("sdrop") {
-        Some(Value::String(s)) => Keycode::from_name(s).expect("Unable to find keycode for sdrop"),
-        None => Keycode::Down,
-        _ => panic!("Malformed sdrop value"),
+    let randomizer = match tab.get("randomizer") {
+        Some(v @ Value::String(s)) => RandomizerKind::from_name(s).ok_or_else(|| {
+            malformed(
+                "randomizer",
+                v,
+                "one of \"bag7\", \"bag14\", \"bag7+1\", \"random\" or \"tgm\"",
+            )
+        })?,
+        None => RandomizerKind::SevenBag,
+        Some(v) => return Err(malformed("randomizer", v, "a randomizer name")),
     };
 
-    let hdrop = match keys.get("hdrop") {
-        Some(Value::String(s)) => Keycode::from_name(s).expect("Unable to find keycode for hdrop"),
-        None => Keycode::Up,
-        _ => panic!("Malformed hdrop value"),
+    let gravity_mode = match tab.get("gravity") {
+        Some(Value::String(s)) if s == "off" => GravityMode::Off,
+        Some(Value::String(s)) if s == "20g" => GravityMode::Constant(20.0),
+        Some(Integer(l)) if u32::try_from(*l).is_ok() => GravityMode::Level(*l as u32),
+        Some(Value::Float(g)) if g.is_finite() && *g >= 0.0 => GravityMode::Constant(*g),
+        None => GravityMode::Off,
+        Some(v) => {
+            return Err(malformed(
+                "gravity",
+                v,
+                "\"off\", \"20g\", a level or a number of cells per frame",
+            ))
+        }
     };
 
-    let rrot = match keys.get("rrot") {
-        Some(Value::String(s)) => Keycode::from_name(s).expect("Unable to find keycode for rrot"),
-        None => Keycode::X,
-        _ => panic!("Malformed rrot value"),
+    let kicks_180 = match tab.get("kicks_180") {
+        Some(v @ Value::String(s)) => Kicks180::from_name(s).ok_or_else(|| {
+            malformed(
+                "kicks_180",
+                v,
+                "one of \"none\", \"tetrio\" or \"nullpomino\"",
+            )
+        })?,
+        None => Kicks180::Tetrio,
+        Some(v) => return Err(malformed("kicks_180", v, "a kick table name")),
     };
 
-    let lrot = match keys.get("lrot") {
-        Some(Value::String(s)) => Keycode::from_name(s).expect("Unable to find keycode for lrot"),
-        None => Keycode::Z,
-        _ => panic!("Malformed lrot value"),
+    let hold_mode = match tab.get("hold_mode") {
+        Some(v @ Value::String(s)) => HoldMode::from_name(s).ok_or_else(|| {
+            malformed(
+                "hold_mode",
+                v,
+                "one of \"off\", \"guideline\", \"infinite\" or \"swap_next\"",
+            )
+        })?,
+        None => HoldMode::Guideline,
+        Some(v) => return Err(malformed("hold_mode", v, "a hold mode name")),
     };
 
-    let hold = match keys.get("hold") {
-        Some(Value::String(s)) => Keycode::from_name(s).expect("Unable to find keycode for hold"),
-        None => Keycode::LShift,
-        _ => panic!("Malformed hold value"),
+    let rotation_system = match tab.get("rotation_system") {
+        Some(v @ Value::String(s)) => RotationSystemKind::from_name(s)
+            .ok_or_else(|| {
+                malformed(
+                    "rotation_system",
+                    v,
+                    "one of \"srs\", \"srs+\", \"ars\", \"nrs\" or a ruleset file",
+                )
+            })?
+            .ruleset(),
+        Some(Value::Table(t)) => Ruleset::from_table(t)?,
+        None => RotationSystemKind::Srs.ruleset(),
+        Some(v) => return Err(malformed("rotation_system", v, "a rotation system name")),
+    };
+
+    let lock_delay = parse_u32(&tab, "lock_delay", Gravity::off().lock_delay())?;
+    let lock_resets = parse_u32(&tab, "lock_resets", Gravity::off().max_resets())?;
+    let sprint_target = parse_u32(&tab, "sprint_lines", 40)?;
+
+    let previews = match parse_u32(&tab, "previews", 5)? {
+        n @ 1..=7 => n as usize,
+        n => {
+            return Err(malformed(
+                "previews",
+                &Integer(n as i64),
+                "an integer from 1 to 7",
+            ))
+        }
+    };
+
+    let tick_rate = match parse_u32(&tab, "tick_rate", 60)? {
+        n @ 10..=1000 => n,
+        n => {
+            return Err(malformed(
+                "tick_rate",
+                &Integer(n as i64),
+                "an integer from 10 to 1000",
+            ))
+        }
     };
 
-    Config {
-        dir_delay,
-        keys: KeyConfig {
-            left,
-            right,
-            sdrop,
-            hdrop,
-            lrot,
-            rrot,
-            hold,
+    let fps = match tab.get("fps") {
+        Some(Value::String(s)) if s == "vsync" => None,
+        Some(v @ Integer(f)) => match u32::try_from(*f) {
+            Ok(f) if f > 0 => Some(f),
+            _ => return Err(malformed("fps", v, "a positive integer or \"vsync\"")),
         },
-    }
+        None => None,
+        Some(v) => return Err(malformed("fps", v, "a positive integer or \"vsync\"")),
+    };
+
+    let default_dims = Dimensions::default();
+    let dimensions = Dimensions {
+        width: parse_size(&tab, "board_width", default_dims.width, 4)?,
+        height: parse_size(&tab, "board_height", default_dims.height, 4)?,
+        buffer: parse_size(&tab, "board_buffer", default_dims.buffer, 0)?,
+    };
+
+    let sprint_lines = match tab.get("mode") {
+        Some(Value::String(s)) if s == "endless" => None,
+        Some(Value::String(s)) if s == "sprint" => Some(sprint_target),
+        None => None,
+        Some(v) => return Err(malformed("mode", v, "\"endless\" or \"sprint\"")),
+    };
+
+    let finesse_trainer = match tab.get("finesse") {
+        Some(Value::String(s)) if s == "count" => false,
+        Some(Value::String(s)) if s == "trainer" => true,
+        None => false,
+        Some(v) => return Err(malformed("finesse", v, "\"count\" or \"trainer\"")),
+    };
+
+    let bot = match tab.get("bot") {
+        Some(Value::Table(t)) => Some(parse_bot(t)?),
+        None => None,
+        Some(v) => return Err(malformed("bot", v, "a table")),
+    };
+
+    let keys = match tab.get("keys") {
+        Some(Value::Table(t)) => t,
+        None => &Table::new(),
+        Some(v) => return Err(malformed("keys", v, "a table")),
+    };
+
+    Ok(Config {
+        handling: Handling {
+            das: Duration::from_millis(das as u64),
+            arr: Duration::from_millis(arr as u64),
+            sdf,
+            dcd: Duration::from_millis(dcd as u64),
+        },
+        seed,
+        randomizer,
+        gravity: Gravity::new(gravity_mode, lock_delay, lock_resets),
+        kicks_180,
+        hold_mode,
+        rotation_system,
+        dimensions,
+        sprint_lines,
+        previews,
+        tick_rate,
+        fps,
+        finesse_trainer,
+        bot,
+        keys: keys.clone(),
+    })
 }
@@ -0,0 +1,35 @@
+// Events emitted by `State` as the game is played, for frontends, stats and
+// anything else that reacts to the game without polling its state
+
+use crate::{
+    scoring::LockEvent,
+    state::{GameOverReason, Piece, PieceType, RotationState},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+    /// A piece entered the board
+    Spawn(PieceType),
+    /// The active piece rotated, `kick` being the index into the kick table
+    /// of the offset that worked (0 for the first)
+    Rotate {
+        from: RotationState,
+        to: RotationState,
+        kick: usize,
+    },
+    /// `held` went into hold, swapping out `released` if there was one. With
+    /// `HoldMode::SwapNext` `held` went back into the queue and `released`
+    /// is the piece that was next
+    Hold {
+        held: PieceType,
+        released: Option<PieceType>,
+    },
+    /// A piece locked at its position, with what was scored for it. Not
+    /// emitted for a lock out, which ends the game instead
+    Lock { piece: Piece, lock: LockEvent },
+    /// Full rows removed after a lock, counted from the top of the hidden
+    /// buffer as they were before the rows above fell
+    Clear { rows: Vec<usize> },
+    /// The game ended by the stack reaching the top
+    TopOut(GameOverReason),
+}
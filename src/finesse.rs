@@ -0,0 +1,177 @@
+// Finesse is placing each piece with as few key presses as possible, see
+// https://tetris.wiki/Finesse. Each piece is judged from where it spawned
+// against the fewest inputs `movegen` finds for where it locked, as if
+// gravity were off. Holding a direction counts as one press however far the
+// piece moves, and holding starts the count over for the piece that comes
+// out of hold
+
+use crate::{
+    event::GameEvent,
+    input::Key,
+    movegen::{cells, reachable, Placement},
+    state::{PieceType, State, Status},
+};
+
+/// A piece placed with more presses than it needed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fault {
+    pub typ: PieceType,
+    pub presses: u32,
+    /// A shortest way to the same placement
+    pub placement: Placement,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Finesse {
+    /// Placements the active piece could reach from where it spawned
+    placements: Option<Vec<Placement>>,
+    presses: u32,
+    pieces: u32,
+    faults: u32,
+    wasted: u32,
+    last_fault: Option<Fault>,
+}
+
+impl Finesse {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a key press for the active piece. Hard drop and hold aren't
+    /// counted, every piece needs the one and the other starts over
+    pub fn input(&mut self, key: Key, press: bool, state: &State) {
+        if press && state.status() == Status::Playing && !matches!(key, Key::HDrop | Key::Hold) {
+            self.presses += 1;
+        }
+    }
+
+    /// Follow the game, which has to be called with every event as it
+    /// happens so that spawns see the board they spawned on
+    pub fn event(&mut self, event: &GameEvent, state: &State) {
+        match event {
+            GameEvent::Spawn(typ) => {
+                let start = state.spawn_position(*typ);
+                self.placements = Some(reachable(state, start));
+                self.presses = 0;
+            }
+            GameEvent::Hold { .. } => {
+                self.placements = None;
+                self.presses = 0;
+            }
+            GameEvent::Lock { piece, lock } => {
+                let placements = match self.placements.take() {
+                    Some(p) => p,
+                    None => return,
+                };
+                // A spin the search didn't find still counts for where the
+                // piece ended up
+                let locked = cells(state, *piece);
+                let covers = |p: &&Placement| cells(state, p.piece) == locked;
+                let best = placements
+                    .iter()
+                    .filter(covers)
+                    .find(|p| p.spin == lock.spin)
+                    .or_else(|| placements.iter().find(covers));
+
+                self.pieces += 1;
+                if let Some(best) = best {
+                    let needed = best.steps.len() as u32;
+                    if self.presses > needed {
+                        self.faults += 1;
+                        self.wasted += self.presses - needed;
+                        self.last_fault = Some(Fault {
+                            typ: piece.typ(),
+                            presses: self.presses,
+                            placement: best.clone(),
+                        });
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Pieces judged so far
+    pub fn pieces(&self) -> u32 {
+        self.pieces
+    }
+
+    /// Pieces placed with more presses than needed
+    pub fn faults(&self) -> u32 {
+        self.faults
+    }
+
+    /// Presses beyond what every piece needed, over all faults
+    pub fn wasted(&self) -> u32 {
+        self.wasted
+    }
+
+    /// The latest fault, if there's been one since the last call
+    pub fn take_fault(&mut self) -> Option<Fault> {
+        self.last_fault.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::HoldMode;
+
+    fn follow(finesse: &mut Finesse, state: &mut State) {
+        for event in state.take_events() {
+            finesse.event(&event, state);
+        }
+    }
+
+    fn game(finesse: &mut Finesse) -> State {
+        let mut state = State::with_seed(0);
+        state.set_hold_mode(HoldMode::Disabled);
+        state.start();
+        state.spawn_piece(PieceType::T);
+        follow(finesse, &mut state);
+        state
+    }
+
+    fn press(finesse: &mut Finesse, state: &mut State, key: Key) {
+        finesse.input(key, true, state);
+        finesse.input(key, false, state);
+        match key {
+            Key::Left => state.piece_left(),
+            Key::Right => state.piece_right(),
+            Key::HDrop => state.drop(true),
+            _ => unreachable!(),
+        }
+        follow(finesse, state);
+    }
+
+    #[test]
+    fn counts_wasted_presses() {
+        let mut finesse = Finesse::new();
+        let mut state = game(&mut finesse);
+        // Two columns left takes two presses, not four
+        for key in [Key::Left, Key::Left, Key::Left, Key::Right, Key::HDrop] {
+            press(&mut finesse, &mut state, key);
+        }
+        assert_eq!(finesse.pieces(), 1);
+        assert_eq!(finesse.faults(), 1);
+        assert_eq!(finesse.wasted(), 2);
+
+        let fault = finesse.take_fault().unwrap();
+        assert_eq!(fault.typ, PieceType::T);
+        assert_eq!(fault.presses, 4);
+        assert_eq!(fault.placement.steps.len(), 2);
+        assert_eq!(finesse.take_fault(), None);
+    }
+
+    #[test]
+    fn no_fault_for_the_fewest_presses() {
+        let mut finesse = Finesse::new();
+        let mut state = game(&mut finesse);
+        for key in [Key::Left, Key::Left, Key::HDrop] {
+            press(&mut finesse, &mut state, key);
+        }
+        assert_eq!(finesse.pieces(), 1);
+        assert_eq!(finesse.faults(), 0);
+        assert_eq!(finesse.take_fault(), None);
+    }
+}
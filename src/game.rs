@@ -0,0 +1,553 @@
+// Everything about running a game that doesn't depend on the frontend:
+// loading the config, the sprint timer, recording and playing back replays,
+// bots and the main loop
+
+use std::{
+    collections::VecDeque,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use rand::{thread_rng, Rng};
+use toml::Table;
+
+use crate::{
+    ai::Ai,
+    config::{config_warnings, get_config_table, parse_map, BotEngine, Config},
+    event::GameEvent,
+    finesse::{Fault, Finesse},
+    input::{Action, KeyEvent, KeyState},
+    movegen::Step,
+    render::Renderer,
+    replay::{Replay, ReplayInput},
+    sprint::{format_time, Sprint, SPLIT_LINES},
+    state::{GameOverReason, State, Status},
+    stats::Stats,
+    tbp::{first_playable, Bot, Move},
+    TetrErr,
+};
+
+/// Input read by a frontend
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    /// A bound key was pressed (`true`) or released
+    Action(Action, bool),
+    Quit,
+}
+
+pub trait Frontend: Renderer {
+    /// Input received since the last call, along with when it happened
+    fn poll(&mut self) -> Result<Vec<(Instant, Input)>, TetrErr>;
+
+    /// Whether `draw` waits for the display to refresh, which then paces the
+    /// loop unless `fps` is set
+    fn vsync(&self) -> bool {
+        false
+    }
+
+    /// Name of a key bound to `action`, used in the status text
+    fn binding_name(&self, action: Action) -> String;
+
+    /// Show a line of text that isn't part of the status, e.g. split times
+    fn message(&mut self, msg: &str) {
+        println!("{}", msg);
+    }
+}
+
+/// Load the config, from `playback` if given. Also returns the raw table,
+/// which new replays are recorded with
+pub fn load_config(playback: Option<&Replay>) -> Result<(Table, Config), TetrErr> {
+    match playback {
+        Some(replay) => Ok((Table::new(), replay.config()?)),
+        None => {
+            let table = get_config_table()?;
+            for warning in config_warnings(&table) {
+                eprintln!("warning: {}", warning);
+            }
+            let config = parse_map(table.clone())?;
+            Ok((table, config))
+        }
+    }
+}
+
+fn new_game(config: &Config) -> State {
+    let seed = config.seed().unwrap_or_else(|| thread_rng().gen());
+    let mut state = State::with_randomizer(seed, config.randomizer().build());
+    state.set_gravity(config.gravity());
+    state.set_tick_rate(config.tick_rate());
+    state.set_kicks_180(config.kicks_180());
+    state.set_hold_mode(config.hold_mode());
+    state.set_rotation_system(Box::new(config.rotation_system().clone()));
+    state.set_dimensions(config.dimensions());
+    state.set_line_goal(config.sprint_lines());
+    state
+}
+
+fn status_text(state: &State, frontend: &dyn Frontend, sprint: Option<&Sprint>) -> String {
+    let restart = frontend.binding_name(Action::Restart);
+    let status = match state.status() {
+        Status::Ready => format!("press {} to start", restart),
+        Status::Playing => match sprint {
+            Some(sprint) => format!(
+                "{}/{} lines - {}",
+                state.scoring().lines(),
+                sprint.target(),
+                format_time(sprint.elapsed())
+            ),
+            None => "playing".to_string(),
+        },
+        Status::Paused => format!(
+            "paused, press {} to resume",
+            frontend.binding_name(Action::Pause)
+        ),
+        Status::Finished => format!(
+            "finished in {}, press {} to restart",
+            format_time(sprint.map_or(Duration::ZERO, |s| s.elapsed())),
+            restart
+        ),
+        Status::Over(reason) => format!(
+            "game over ({}), press {} to restart",
+            match reason {
+                GameOverReason::BlockOut => "block out",
+                GameOverReason::LockOut => "lock out",
+            },
+            restart
+        ),
+    };
+    format!(
+        "tet.rs - seed {} - score {} - {}",
+        state.seed(),
+        state.scoring().score(),
+        status
+    )
+}
+
+/// How far the simulation can fall behind before the missed ticks are
+/// dropped, e.g. after the process was suspended
+const MAX_BEHIND: Duration = Duration::from_millis(250);
+
+fn apply_input(
+    input: ReplayInput,
+    state: &mut State,
+    keys: &mut KeyState,
+    sprint: &mut Option<Sprint>,
+    finesse: &mut Finesse,
+    stats: &mut Stats,
+) {
+    match input {
+        ReplayInput::Pause => state.toggle_pause(),
+        ReplayInput::Key(key, press) => {
+            if press && state.status() == Status::Playing {
+                if let Some(sprint) = sprint.as_mut() {
+                    sprint.start();
+                }
+            }
+            finesse.input(key, press, state);
+            if state.status() == Status::Playing {
+                stats.input(key, press);
+            }
+            keys.update(KeyEvent { key, press }, state)
+        }
+    }
+}
+
+/// Take the events `state` emitted into `events`, showing them to `finesse`
+/// while the board is still as they left it
+fn take_events(
+    state: &mut State,
+    finesse: &mut Finesse,
+    stats: &mut Stats,
+    events: &mut Vec<GameEvent>,
+) {
+    for event in state.take_events() {
+        finesse.event(&event, state);
+        stats.event(&event);
+        events.push(event);
+    }
+}
+
+fn stats_panel(stats: &Stats, finesse: &Finesse) -> Vec<(&'static str, String)> {
+    vec![
+        ("pieces", stats.pieces().to_string()),
+        ("pps", format!("{:.2}", stats.pps())),
+        ("kpp", format!("{:.2}", stats.kpp())),
+        ("lines", stats.lines().to_string()),
+        ("apm", format!("{:.1}", stats.apm())),
+        ("time", format_time(stats.elapsed())),
+        ("faults", finesse.faults().to_string()),
+    ]
+}
+
+/// What the trainer shows for a finesse fault, e.g. "T took 4 keys, 2 needed:
+/// left (held), x"
+fn fault_text(fault: &Fault, frontend: &dyn Frontend) -> String {
+    let steps: Vec<String> = fault
+        .placement
+        .steps
+        .iter()
+        .map(|step| {
+            let key = frontend.binding_name(Action::Game(step.key()));
+            match step {
+                Step::DasLeft | Step::DasRight => format!("{} (held)", key),
+                _ => key,
+            }
+        })
+        .collect();
+    format!(
+        "{} took {} keys, {} needed: {}",
+        fault.typ.name(),
+        fault.presses,
+        steps.len(),
+        match steps.is_empty() {
+            true => "just drop".to_string(),
+            false => steps.join(", "),
+        }
+    )
+}
+
+fn save_recording(
+    recording: &mut Option<Replay>,
+    frontend: &mut dyn Frontend,
+) -> Result<(), TetrErr> {
+    if let Some(replay) = recording.take() {
+        let path = replay.save_default()?;
+        frontend.message(&format!("replay saved to {}", path.display()));
+    }
+    Ok(())
+}
+
+/// What makes a bot's moves
+enum Engine {
+    External(Bot),
+    Builtin(Ai),
+}
+
+impl Engine {
+    fn name(&self) -> &str {
+        match self {
+            Engine::External(bot) => bot.name(),
+            Engine::Builtin(_) => "built-in bot",
+        }
+    }
+}
+
+/// A bot playing the game or giving hints
+struct BotPlayer {
+    engine: Engine,
+    hint: bool,
+    delay: Duration,
+    /// Pieces spawned when the bot was last told about the game, `None`
+    /// if it hasn't been told about this one
+    synced: Option<u64>,
+    /// What an external bot suggested, the built-in one decides when it
+    /// plays
+    moves: Option<Vec<Move>>,
+    last_move: Instant,
+    /// The bot had no valid move, it's left alone until the next game
+    stuck: bool,
+}
+
+impl BotPlayer {
+    fn reset(&mut self) {
+        self.synced = None;
+        self.moves = None;
+        self.stuck = false;
+    }
+
+    fn update(
+        &mut self,
+        state: &mut State,
+        sprint: &mut Option<Sprint>,
+        frontend: &mut dyn Frontend,
+    ) -> Result<(), TetrErr> {
+        if state.status() != Status::Playing || self.stuck {
+            return Ok(());
+        }
+
+        // Hints are for whatever the player did last, so the bot is told
+        // about the whole game again for every new piece
+        let resync = match self.synced {
+            None => true,
+            Some(spawns) => self.hint && spawns != state.spawns(),
+        };
+        if resync {
+            self.synced = Some(state.spawns());
+            self.moves = None;
+            match &mut self.engine {
+                Engine::External(bot) => {
+                    bot.start(state)?;
+                    frontend.hint(None);
+                }
+                Engine::Builtin(ai) if self.hint => {
+                    frontend.hint(ai.choose(state).map(|p| p.piece));
+                }
+                Engine::Builtin(_) => (),
+            }
+        }
+
+        if let Engine::External(bot) = &mut self.engine {
+            if let Some(moves) = bot.poll(state)? {
+                if self.hint {
+                    let best = first_playable(state, &moves);
+                    frontend.hint(best.map(|(_, p)| p.piece));
+                }
+                self.moves = Some(moves);
+            }
+        }
+
+        if self.hint || self.last_move.elapsed() < self.delay {
+            return Ok(());
+        }
+        let ready = match self.engine {
+            Engine::External(_) => self.moves.is_some(),
+            Engine::Builtin(_) => true,
+        };
+        if !ready {
+            return Ok(());
+        }
+
+        if let Some(sprint) = sprint.as_mut() {
+            sprint.start();
+        }
+        let played = match &mut self.engine {
+            Engine::External(bot) => bot.play(state, &self.moves.take().unwrap_or_default())?,
+            Engine::Builtin(ai) => match ai.choose(state) {
+                Some(placement) => {
+                    placement.play(state);
+                    true
+                }
+                None => false,
+            },
+        };
+        if !played {
+            frontend.message(&format!("{} has no valid move", self.engine.name()));
+            self.stuck = true;
+        }
+        self.last_move = Instant::now();
+        Ok(())
+    }
+}
+
+/// Play games until the frontend quits, or watch `playback`. `table` is the
+/// raw config the games are recorded with.
+///
+/// The game advances in fixed ticks of `1 / tick_rate` seconds. Inputs are
+/// applied on the tick they happened during, going by their timestamps, so a
+/// game plays out the same however often it's drawn
+pub fn run(
+    frontend: &mut dyn Frontend,
+    table: &Table,
+    config: &Config,
+    playback: Option<&Replay>,
+) -> Result<(), TetrErr> {
+    let tick = Duration::from_secs_f64(1.0 / config.tick_rate() as f64);
+    let draw_interval = match (config.fps(), frontend.vsync()) {
+        (Some(fps), _) => Some(Duration::from_secs_f64(1.0 / fps as f64)),
+        (None, true) => None,
+        // Nothing to sync to, draw once per tick
+        (None, false) => Some(tick),
+    };
+
+    let mut state = new_game(config);
+    let mut finesse = Finesse::new();
+    let mut stats = Stats::new();
+    let mut events = Vec::new();
+    let mut keys = KeyState::new(config.handling());
+    let mut sprint = config.sprint_lines().map(Sprint::new);
+    let mut recording = None;
+    let mut frame = 0u64;
+    let mut next_event = 0;
+    let mut status = None;
+    let mut pending = VecDeque::new();
+    let mut tick_end = Instant::now() + tick;
+    let mut next_draw = Instant::now();
+
+    if playback.is_some() {
+        state.start();
+    }
+
+    let mut bot = match (config.bot(), playback) {
+        (Some(bot), None) => {
+            let engine = match &bot.engine {
+                BotEngine::Command(command) => {
+                    Engine::External(Bot::spawn(command, config.previews())?)
+                }
+                BotEngine::Builtin(weights) => Engine::Builtin(Ai::new(*weights)),
+            };
+            let player = BotPlayer {
+                engine,
+                hint: bot.hint,
+                delay: bot.delay,
+                synced: None,
+                moves: None,
+                last_move: Instant::now(),
+                stuck: false,
+            };
+            frontend.message(&format!(
+                "{} is {}",
+                player.engine.name(),
+                if player.hint {
+                    "giving hints"
+                } else {
+                    "playing"
+                }
+            ));
+            Some(player)
+        }
+        _ => None,
+    };
+    // Bot moves aren't inputs, so games it plays can't be recorded
+    let bot_plays = bot.as_ref().is_some_and(|b| !b.hint);
+
+    'running: loop {
+        for (time, input) in frontend.poll()? {
+            let (action, press) = match input {
+                Input::Quit => break 'running,
+                _ if playback.is_some() => continue,
+                Input::Action(action, press) => (action, press),
+            };
+
+            match action {
+                Action::Restart if press => {
+                    save_recording(&mut recording, frontend)?;
+                    if state.status() != Status::Ready {
+                        state = new_game(config);
+                        finesse = Finesse::new();
+                        stats = Stats::new();
+                        events.clear();
+                        sprint = config.sprint_lines().map(Sprint::new);
+                    }
+                    keys = KeyState::new(config.handling());
+                    state.start();
+                    if !bot_plays {
+                        recording = Some(Replay::new(table.clone(), state.seed()));
+                    }
+                    if let Some(bot) = bot.as_mut() {
+                        bot.reset();
+                    }
+                    pending.clear();
+                    frame = 0;
+                    tick_end = Instant::now() + tick;
+                }
+                Action::Pause if press => pending.push_back((time, ReplayInput::Pause)),
+                Action::Game(_) if bot_plays => (),
+                Action::Game(key) => pending.push_back((time, ReplayInput::Key(key, press))),
+                _ => (),
+            }
+        }
+
+        if let Some(bot) = bot.as_mut() {
+            bot.update(&mut state, &mut sprint, frontend)?;
+            take_events(&mut state, &mut finesse, &mut stats, &mut events);
+        }
+
+        let now = Instant::now();
+        if now > tick_end + MAX_BEHIND {
+            tick_end = now;
+        }
+        while tick_end <= now {
+            if let Some(replay) = playback {
+                while let Some(event) = replay.events().get(next_event) {
+                    if event.frame > frame {
+                        break;
+                    }
+                    apply_input(
+                        event.input,
+                        &mut state,
+                        &mut keys,
+                        &mut sprint,
+                        &mut finesse,
+                        &mut stats,
+                    );
+                    take_events(&mut state, &mut finesse, &mut stats, &mut events);
+                    next_event += 1;
+                }
+            }
+
+            while let Some(&(time, input)) = pending.front() {
+                if time >= tick_end {
+                    break;
+                }
+                pending.pop_front();
+                apply_input(
+                    input,
+                    &mut state,
+                    &mut keys,
+                    &mut sprint,
+                    &mut finesse,
+                    &mut stats,
+                );
+                take_events(&mut state, &mut finesse, &mut stats, &mut events);
+                if let Some(replay) = recording.as_mut() {
+                    replay.push(frame, input);
+                }
+            }
+
+            keys.tick(&mut state, tick);
+            state.tick();
+            take_events(&mut state, &mut finesse, &mut stats, &mut events);
+            if state.status() == Status::Playing {
+                stats.advance(tick);
+            }
+            if let Some(sprint) = sprint.as_mut() {
+                if matches!(state.status(), Status::Playing | Status::Finished) {
+                    sprint.advance(tick, state.scoring().lines());
+                }
+            }
+            frame += 1;
+            tick_end += tick;
+        }
+
+        take_events(&mut state, &mut finesse, &mut stats, &mut events);
+        for event in events.drain(..) {
+            frontend.event(&event);
+        }
+        if let Some(fault) = finesse.take_fault() {
+            if config.finesse_trainer() {
+                frontend.message(&fault_text(&fault, frontend));
+            }
+        }
+        frontend.stats(&stats_panel(&stats, &finesse));
+
+        let mut text = status_text(&state, frontend, sprint.as_ref());
+        if playback.is_some() {
+            text = format!("{} (replay)", text);
+        }
+        if status != Some(state.status()) {
+            status = Some(state.status());
+            frontend.message(&text);
+            if let (Status::Finished, Some(sprint)) = (state.status(), sprint.as_ref()) {
+                for (i, split) in sprint.splits().iter().enumerate() {
+                    frontend.message(&format!(
+                        "{} lines: {}",
+                        (i + 1) * SPLIT_LINES as usize,
+                        format_time(*split)
+                    ));
+                }
+            }
+            if matches!(state.status(), Status::Over(_) | Status::Finished) {
+                save_recording(&mut recording, frontend)?;
+            }
+        }
+
+        match draw_interval {
+            // Drawing blocks until the display refreshes
+            None => frontend.draw(&mut state, &text)?,
+            Some(interval) => {
+                let now = Instant::now();
+                if now >= next_draw {
+                    frontend.draw(&mut state, &text)?;
+                    next_draw = (next_draw + interval).max(now);
+                }
+
+                let wake = tick_end.min(next_draw);
+                let now = Instant::now();
+                if wake > now {
+                    sleep(wake - now);
+                }
+            }
+        }
+    }
+
+    save_recording(&mut recording, frontend)
+}
@@ -0,0 +1,71 @@
+// Gravity curve and lock delay follow https://tetris.wiki/Marathon and
+// https://tetris.wiki/Lock_delay. All timing is in frames, see `State::tick`
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GravityMode {
+    /// Pieces only move down when dropped and never lock on their own
+    Off,
+    /// Guideline speed curve for the given level, 20G from level 20 onwards
+    Level(u32),
+    /// Fixed number of cells per frame, e.g. `Constant(20.0)` for 20G
+    Constant(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gravity {
+    mode: GravityMode,
+    lock_delay: u32,
+    max_resets: u32,
+}
+
+impl Default for Gravity {
+    fn default() -> Self {
+        Self::off()
+    }
+}
+
+impl Gravity {
+    pub fn new(mode: GravityMode, lock_delay: u32, max_resets: u32) -> Self {
+        Gravity {
+            mode,
+            lock_delay,
+            max_resets,
+        }
+    }
+
+    pub fn off() -> Self {
+        Self::new(GravityMode::Off, 30, 15)
+    }
+
+    pub fn mode(&self) -> GravityMode {
+        self.mode
+    }
+
+    /// Frames a piece may rest on the stack before it locks
+    pub fn lock_delay(&self) -> u32 {
+        self.lock_delay
+    }
+
+    /// Number of times moving or rotating may restart the lock delay before
+    /// the piece reaches a new lowest row
+    pub fn max_resets(&self) -> u32 {
+        self.max_resets
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.mode != GravityMode::Off
+    }
+
+    pub fn cells_per_frame(&self) -> f64 {
+        match self.mode {
+            GravityMode::Off => 0.0,
+            GravityMode::Level(l) if l >= 20 => 20.0,
+            GravityMode::Level(l) => {
+                let l = l.max(1) as f64;
+                let secs_per_row = (0.8 - (l - 1.0) * 0.007).powf(l - 1.0);
+                1.0 / (secs_per_row * 60.0)
+            }
+            GravityMode::Constant(g) => g,
+        }
+    }
+}
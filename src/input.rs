@@ -1,10 +1,11 @@
 // My goal is to make keybindings fully customizable bc I hate
 // programs that don't.
 
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
 
-use crate::{config::Config, state::State};
+use crate::state::State;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Key {
     Left,
     Right,
@@ -12,63 +13,192 @@ pub enum Key {
     SDrop,
     RRot,
     LRot,
+    Rot180,
     Hold, // Later, i hope
 }
 
+impl Key {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Key::Left => "left",
+            Key::Right => "right",
+            Key::HDrop => "hdrop",
+            Key::SDrop => "sdrop",
+            Key::RRot => "rrot",
+            Key::LRot => "lrot",
+            Key::Rot180 => "rot180",
+            Key::Hold => "hold",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "left" => Some(Key::Left),
+            "right" => Some(Key::Right),
+            "hdrop" => Some(Key::HDrop),
+            "sdrop" => Some(Key::SDrop),
+            "rrot" => Some(Key::RRot),
+            "lrot" => Some(Key::LRot),
+            "rot180" => Some(Key::Rot180),
+            "hold" => Some(Key::Hold),
+            _ => None,
+        }
+    }
+}
+
+/// What a binding does when pressed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Game(Key),
+    Pause,
+    Restart,
+}
+
 pub struct KeyEvent {
-    pub(crate) key: Key,
-    pub(crate) press: bool,
+    pub key: Key,
+    pub press: bool,
+}
+
+/// Handling settings, see https://tetris.wiki/DAS
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Handling {
+    /// Delayed auto shift, how long a direction is held before it repeats
+    pub das: Duration,
+    /// Auto repeat rate, time between moves once DAS is charged. Zero snaps
+    /// the piece to the wall
+    pub arr: Duration,
+    /// Soft drop factor, a multiple of the current gravity. `None` drops
+    /// instantly
+    pub sdf: Option<u32>,
+    /// DAS cut delay, how long auto shift pauses after a rotation or spawn
+    pub dcd: Duration,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das: Duration::from_millis(150),
+            arr: Duration::ZERO,
+            sdf: None,
+            dcd: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Dir {
+    Left,
+    Right,
 }
 
+// Soft drop speed with gravity off is based on guideline level 1
+const MIN_SOFT_DROP_G: f64 = 1.0 / 60.0;
+const FRAME_SECS: f64 = 1.0 / 60.0;
+
 pub struct KeyState {
-    config: Config,
+    handling: Handling,
 
     left: bool,
     right: bool,
+    // Direction being auto shifted, the last one pressed if both are held
+    dir: Option<Dir>,
+    das_charge: Duration,
+    arr_charge: Duration,
+    das_cut: Duration,
+    spawns: u64,
 
     sdrop: bool,
-
-    left_press: Option<SystemTime>,
-    right_press: Option<SystemTime>,
+    sdrop_cells: f64,
 }
 
 impl KeyState {
-    pub fn new(config: Config) -> Self {
+    pub fn new(handling: Handling) -> Self {
         KeyState {
-            config,
+            handling,
             left: false,
             right: false,
+            dir: None,
+            das_charge: Duration::ZERO,
+            arr_charge: Duration::ZERO,
+            das_cut: Duration::ZERO,
+            spawns: 0,
             sdrop: false,
-            left_press: None,
-            right_press: None,
+            sdrop_cells: 0.0,
         }
     }
 
-    pub fn handle_special(&mut self, state: &mut State) {
-        if self.sdrop {
-            state.drop(false)
+    fn shift(&self, state: &mut State) {
+        match self.dir {
+            Some(Dir::Left) => state.piece_left(),
+            Some(Dir::Right) => state.piece_right(),
+            None => (),
         }
+    }
 
-        if let Some(st) = self.left_press {
-            if self.left
-                && SystemTime::now()
-                    .duration_since(st)
-                    .expect("Something messed up")
-                    > Duration::from_millis(self.config.dir_delay() as u64)
-            {
-                state.snap_left();
-            }
+    fn snap(&self, state: &mut State) {
+        match self.dir {
+            Some(Dir::Left) => state.snap_left(),
+            Some(Dir::Right) => state.snap_right(),
+            None => (),
+        }
+    }
+
+    fn set_dir(&mut self, dir: Option<Dir>) {
+        self.dir = dir;
+        self.das_charge = Duration::ZERO;
+        self.arr_charge = Duration::ZERO;
+    }
+
+    /// Advance held inputs by `dt`. The caller steps this with a fixed `dt`
+    /// so that handling is reproducible
+    pub fn tick(&mut self, state: &mut State, dt: Duration) {
+        if state.spawns() != self.spawns {
+            self.spawns = state.spawns();
+            self.das_cut = self.handling.dcd;
         }
-        if let Some(st) = self.right_press {
-            if self.right
-                && SystemTime::now()
-                    .duration_since(st)
-                    .expect("Something messed up")
-                    > Duration::from_millis(self.config.dir_delay() as u64)
-            {
-                state.snap_right();
+
+        if self.sdrop {
+            match self.handling.sdf {
+                None => state.drop(false),
+                Some(sdf) => {
+                    let g = state.gravity().cells_per_frame().max(MIN_SOFT_DROP_G);
+                    self.sdrop_cells += g * sdf as f64 * dt.as_secs_f64() / FRAME_SECS;
+                    while self.sdrop_cells >= 1.0 {
+                        self.sdrop_cells -= 1.0;
+                        state.piece_down();
+                    }
+                }
             }
         }
+
+        let cut = !self.das_cut.is_zero();
+        self.das_cut = self.das_cut.saturating_sub(dt);
+        if self.dir.is_none() {
+            return;
+        }
+
+        let charged = self.das_charge >= self.handling.das;
+        self.das_charge += dt;
+        if self.das_charge < self.handling.das || cut {
+            return;
+        }
+
+        if self.handling.arr.is_zero() {
+            self.snap(state);
+            return;
+        }
+
+        if charged {
+            self.arr_charge += dt;
+        } else {
+            // First repeat happens as soon as DAS is charged
+            self.shift(state);
+            self.arr_charge = self.das_charge - self.handling.das;
+        }
+        while self.arr_charge >= self.handling.arr {
+            self.arr_charge -= self.handling.arr;
+            self.shift(state);
+        }
     }
 
     pub fn update(&mut self, event: KeyEvent, state: &mut State) {
@@ -76,19 +206,19 @@ impl KeyState {
             Key::Left => {
                 self.left = event.press;
                 if event.press {
-                    self.left_press = Some(SystemTime::now());
+                    self.set_dir(Some(Dir::Left));
                     state.piece_left();
-                } else {
-                    self.left_press = None;
+                } else if self.dir == Some(Dir::Left) {
+                    self.set_dir(self.right.then_some(Dir::Right));
                 }
             }
             Key::Right => {
                 self.right = event.press;
                 if event.press {
-                    self.right_press = Some(SystemTime::now());
+                    self.set_dir(Some(Dir::Right));
                     state.piece_right();
-                } else {
-                    self.right_press = None;
+                } else if self.dir == Some(Dir::Right) {
+                    self.set_dir(self.left.then_some(Dir::Left));
                 }
             }
             Key::HDrop => {
@@ -99,21 +229,33 @@ impl KeyState {
             Key::SDrop => {
                 if event.press {
                     self.sdrop = true;
-                    state.drop(false)
+                    self.sdrop_cells = 0.0;
+                    match self.handling.sdf {
+                        None => state.drop(false),
+                        Some(_) => state.piece_down(),
+                    }
                 } else {
                     self.sdrop = false;
                 }
             }
             Key::RRot => {
                 if event.press {
+                    self.das_cut = self.handling.dcd;
                     state.rotate_right()
                 }
             }
             Key::LRot => {
                 if event.press {
+                    self.das_cut = self.handling.dcd;
                     state.rotate_left()
                 }
             }
+            Key::Rot180 => {
+                if event.press {
+                    self.das_cut = self.handling.dcd;
+                    state.rotate_180()
+                }
+            }
             Key::Hold => {
                 if event.press {
                     state.hold();
@@ -122,3 +264,122 @@ impl KeyState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::PieceType;
+
+    const DT: Duration = Duration::from_millis(10);
+
+    fn handling(das: u64, arr: u64) -> KeyState {
+        KeyState::new(Handling {
+            das: Duration::from_millis(das),
+            arr: Duration::from_millis(arr),
+            ..Handling::default()
+        })
+    }
+
+    /// A T at its spawn position, columns 3 to 5
+    fn game() -> State {
+        let mut state = State::with_seed(0);
+        state.start();
+        state.spawn_piece(PieceType::T);
+        state
+    }
+
+    fn press(keys: &mut KeyState, state: &mut State, key: Key, press: bool) {
+        keys.update(KeyEvent { key, press }, state);
+    }
+
+    fn tick(keys: &mut KeyState, state: &mut State, n: usize) {
+        for _ in 0..n {
+            keys.tick(state, DT);
+        }
+    }
+
+    #[test]
+    fn das_then_arr() {
+        let (mut keys, mut state) = (handling(100, 20), game());
+        press(&mut keys, &mut state, Key::Right, true);
+        assert_eq!(state.piece().x(), 4);
+        tick(&mut keys, &mut state, 9);
+        assert_eq!(state.piece().x(), 4);
+        tick(&mut keys, &mut state, 1);
+        assert_eq!(state.piece().x(), 5);
+        tick(&mut keys, &mut state, 1);
+        assert_eq!(state.piece().x(), 5);
+        tick(&mut keys, &mut state, 1);
+        assert_eq!(state.piece().x(), 6);
+    }
+
+    #[test]
+    fn zero_arr_snaps_to_the_wall() {
+        let (mut keys, mut state) = (handling(100, 0), game());
+        press(&mut keys, &mut state, Key::Right, true);
+        tick(&mut keys, &mut state, 9);
+        assert_eq!(state.piece().x(), 4);
+        tick(&mut keys, &mut state, 1);
+        assert_eq!(state.piece().x(), 7);
+    }
+
+    #[test]
+    fn last_pressed_direction_wins() {
+        let (mut keys, mut state) = (handling(100, 0), game());
+        press(&mut keys, &mut state, Key::Right, true);
+        press(&mut keys, &mut state, Key::Left, true);
+        assert_eq!(state.piece().x(), 3);
+        tick(&mut keys, &mut state, 10);
+        assert_eq!(state.piece().x(), 0);
+
+        // Right takes over again, charging DAS from the start
+        press(&mut keys, &mut state, Key::Left, false);
+        tick(&mut keys, &mut state, 9);
+        assert_eq!(state.piece().x(), 0);
+        tick(&mut keys, &mut state, 1);
+        assert_eq!(state.piece().x(), 7);
+    }
+
+    #[test]
+    fn rotating_cuts_das() {
+        let mut keys = KeyState::new(Handling {
+            das: Duration::from_millis(100),
+            arr: Duration::from_millis(20),
+            dcd: Duration::from_millis(50),
+            ..Handling::default()
+        });
+        let mut state = game();
+        press(&mut keys, &mut state, Key::Left, true);
+        tick(&mut keys, &mut state, 10);
+        assert_eq!(state.piece().x(), 1);
+
+        press(&mut keys, &mut state, Key::RRot, true);
+        tick(&mut keys, &mut state, 6);
+        assert_eq!(state.piece().x(), 1);
+        tick(&mut keys, &mut state, 1);
+        assert_eq!(state.piece().x(), 0);
+    }
+
+    #[test]
+    fn soft_drop_factor() {
+        // With gravity off soft drop is sdf times 1/60 cells a frame
+        let mut keys = KeyState::new(Handling {
+            sdf: Some(6),
+            ..Handling::default()
+        });
+        let mut state = game();
+        let y = state.piece().y();
+        press(&mut keys, &mut state, Key::SDrop, true);
+        assert_eq!(state.piece().y(), y + 1);
+        keys.tick(&mut state, Duration::from_millis(100));
+        assert_eq!(state.piece().y(), y + 1);
+        keys.tick(&mut state, Duration::from_millis(100));
+        assert_eq!(state.piece().y(), y + 2);
+
+        let mut keys = KeyState::new(Handling::default());
+        press(&mut keys, &mut state, Key::SDrop, true);
+        let y = state.piece().y();
+        state.piece_down();
+        assert_eq!(state.piece().y(), y);
+    }
+}
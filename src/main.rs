@@ -1,7 +1,60 @@
+use std::{env, path::PathBuf};
+
 use tetrs::TetrErr;
 
 
 fn main() -> Result<(), TetrErr> {
     //parse config here 
-    tetrs::run()
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let tui = match args.iter().position(|a| a == "--tui") {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    let replay = match args.first().map(String::as_str) {
+        Some("--replay") => match args.get(1) {
+            Some(path) => Some(PathBuf::from(path)),
+            None => {
+                return Err(TetrErr::Str(
+                    "Usage: tetrs [--tui] [--replay <file>]".to_string(),
+                ))
+            }
+        },
+        _ => None,
+    };
+    run(tui, replay)
+}
+
+#[cfg(all(feature = "sdl", feature = "tui"))]
+fn run(tui: bool, replay: Option<PathBuf>) -> Result<(), TetrErr> {
+    match (tui, replay) {
+        (true, replay) => tetrs::run_terminal(replay.as_deref()),
+        (false, Some(path)) => tetrs::play_replay(&path),
+        (false, None) => tetrs::run(),
+    }
+}
+
+#[cfg(all(feature = "sdl", not(feature = "tui")))]
+fn run(tui: bool, replay: Option<PathBuf>) -> Result<(), TetrErr> {
+    match (tui, replay) {
+        (true, _) => Err(TetrErr::Str(
+            "Built without the terminal frontend, enable the tui feature".to_string(),
+        )),
+        (false, Some(path)) => tetrs::play_replay(&path),
+        (false, None) => tetrs::run(),
+    }
+}
+
+#[cfg(all(feature = "tui", not(feature = "sdl")))]
+fn run(_tui: bool, replay: Option<PathBuf>) -> Result<(), TetrErr> {
+    tetrs::run_terminal(replay.as_deref())
+}
+
+#[cfg(not(any(feature = "sdl", feature = "tui")))]
+fn run(_tui: bool, _replay: Option<PathBuf>) -> Result<(), TetrErr> {
+    Err(TetrErr::Str(
+        "Built without a frontend, enable the sdl or tui feature".to_string(),
+    ))
 }
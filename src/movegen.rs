@@ -0,0 +1,261 @@
+// Finds every placement the active piece can reach and the fewest inputs
+// that get it there. Gravity is ignored, as if every input happens before
+// the piece falls, and soft drop is instant like `sdf = "inf"`
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::{
+    input::Key,
+    scoring::Spin,
+    state::{HoldMode, Piece, PieceType, State},
+};
+
+/// A single input. Holding a direction until the piece stops counts as one,
+/// as it does for finesse
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Step {
+    Left,
+    Right,
+    DasLeft,
+    DasRight,
+    RotateRight,
+    RotateLeft,
+    Rotate180,
+    SoftDrop,
+    Hold,
+}
+
+impl Step {
+    /// Searched in this order, so ties go to the earlier ones
+    const MOVES: [Step; 8] = [
+        Step::Left,
+        Step::Right,
+        Step::RotateRight,
+        Step::RotateLeft,
+        Step::Rotate180,
+        Step::DasLeft,
+        Step::DasRight,
+        Step::SoftDrop,
+    ];
+
+    /// Key pressed for this step
+    pub fn key(&self) -> Key {
+        match self {
+            Step::Left | Step::DasLeft => Key::Left,
+            Step::Right | Step::DasRight => Key::Right,
+            Step::RotateRight => Key::RRot,
+            Step::RotateLeft => Key::LRot,
+            Step::Rotate180 => Key::Rot180,
+            Step::SoftDrop => Key::SDrop,
+            Step::Hold => Key::Hold,
+        }
+    }
+}
+
+/// Where a piece can lock, and how to get it there
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub piece: Piece,
+    pub spin: Spin,
+    /// Fewest steps from where the piece started, beginning with
+    /// `Step::Hold` for the piece hold swaps in. The hard drop locking it
+    /// isn't included
+    pub steps: Vec<Step>,
+}
+
+impl Placement {
+    /// Lock the active piece here by pressing each step in turn and hard
+    /// dropping, all at once so gravity can't move it in between
+    pub fn play(&self, state: &mut State) {
+        for step in &self.steps {
+            match step {
+                Step::Left => state.piece_left(),
+                Step::Right => state.piece_right(),
+                Step::DasLeft => state.snap_left(),
+                Step::DasRight => state.snap_right(),
+                Step::RotateRight => state.rotate_right(),
+                Step::RotateLeft => state.rotate_left(),
+                Step::Rotate180 => state.rotate_180(),
+                Step::SoftDrop => state.drop(false),
+                Step::Hold => state.hold(),
+            }
+        }
+        state.drop(true);
+    }
+}
+
+/// Every placement of the active piece from where it is now, followed by
+/// those of the piece hold would swap in if hold can be used. Placements
+/// covering the same cells with the same spin are listed once
+pub fn placements(state: &mut State) -> Vec<Placement> {
+    let mut found = Vec::new();
+    let mut seen = HashSet::new();
+    search(state, state.piece(), &[], &mut found, &mut seen);
+
+    let held = match state.hold_mode() {
+        _ if !state.can_hold() => None,
+        HoldMode::Disabled => None,
+        HoldMode::Guideline | HoldMode::Infinite => Some(
+            state
+                .get_hold_piece()
+                .unwrap_or_else(|| state.get_next_piece()),
+        ),
+        HoldMode::SwapNext => Some(state.get_next_piece()),
+    };
+    if let Some(typ) = held {
+        let piece = state.spawn_position(typ);
+        if state.fits(piece) {
+            search(state, piece, &[Step::Hold], &mut found, &mut seen);
+        }
+    }
+    found
+}
+
+/// Every placement `start` can reach without holding
+pub fn reachable(state: &State, start: Piece) -> Vec<Placement> {
+    let mut found = Vec::new();
+    search(state, start, &[], &mut found, &mut HashSet::new());
+    found
+}
+
+fn shifted(piece: Piece, dx: i32, dy: usize) -> Piece {
+    Piece::new(piece.typ(), piece.rot(), piece.x() + dx, piece.y() + dy)
+}
+
+/// Move `piece` as far as it goes, `None` if it can't move at all
+fn slide(state: &State, piece: Piece, dx: i32, dy: usize) -> Option<Piece> {
+    let mut last = None;
+    let mut next = shifted(piece, dx, dy);
+    while state.fits(next) {
+        last = Some(next);
+        next = shifted(next, dx, dy);
+    }
+    last
+}
+
+/// Where `step` takes `piece`, along with the kick spin detection sees
+fn apply(state: &State, piece: Piece, step: Step) -> Option<(Piece, Option<usize>)> {
+    let rotate = |to| {
+        let (target, kick) = state.rotation_target(piece, to)?;
+        Some((target, Some(State::spin_kick(piece.rot(), to, kick))))
+    };
+    let moved = match step {
+        Step::Left => Some(shifted(piece, -1, 0)).filter(|p| state.fits(*p)),
+        Step::Right => Some(shifted(piece, 1, 0)).filter(|p| state.fits(*p)),
+        Step::DasLeft => slide(state, piece, -1, 0),
+        Step::DasRight => slide(state, piece, 1, 0),
+        Step::SoftDrop => slide(state, piece, 0, 1),
+        Step::RotateRight => return rotate(piece.rot().right()),
+        Step::RotateLeft => return rotate(piece.rot().left()),
+        Step::Rotate180 => return rotate(piece.rot().flip()),
+        Step::Hold => None,
+    };
+    moved.map(|p| (p, None))
+}
+
+/// Cells `piece` covers as `(x, y)` in buffer coordinates, in order. Pieces
+/// covering the same cells are the same placement, however they're rotated
+pub fn cells(state: &State, piece: Piece) -> Vec<(i32, usize)> {
+    let mut cells: Vec<_> = state
+        .rotation()
+        .coords(piece.typ(), piece.rot())
+        .iter()
+        .map(|(r, c)| (piece.x() + *c as i32, piece.y() + r))
+        .collect();
+    cells.sort();
+    cells
+}
+
+/// What tells placements apart
+type Footprint = (PieceType, Vec<(i32, usize)>, Spin);
+
+fn footprint(state: &State, piece: Piece, spin: Spin) -> Footprint {
+    (piece.typ(), cells(state, piece), spin)
+}
+
+/// Breadth first search from `start`, so the first path to reach a
+/// placement is a shortest one. Positions are told apart by the kick they
+/// were reached with, as that decides whether a T spins
+fn search(
+    state: &State,
+    start: Piece,
+    prefix: &[Step],
+    found: &mut Vec<Placement>,
+    seen: &mut HashSet<Footprint>,
+) {
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert((start, None));
+    queue.push_back((start, None, prefix.to_vec()));
+
+    while let Some((piece, kick, steps)) = queue.pop_front() {
+        // Hard dropping only keeps the kick if the piece doesn't move
+        let (locked, spin) = match slide(state, piece, 0, 1) {
+            Some(dropped) => (dropped, Spin::None),
+            None => (piece, state.spin_at(piece, kick)),
+        };
+        if seen.insert(footprint(state, locked, spin)) {
+            found.push(Placement {
+                piece: locked,
+                spin,
+                steps: steps.clone(),
+            });
+        }
+
+        for step in Step::MOVES {
+            if let Some((next, kick)) = apply(state, piece, step) {
+                if visited.insert((next, kick)) {
+                    let mut steps = steps.clone();
+                    steps.push(step);
+                    queue.push_back((next, kick, steps));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty board with `typ` active and hold off
+    fn empty(typ: PieceType) -> State {
+        let mut state = State::with_seed(0);
+        state.set_hold_mode(HoldMode::Disabled);
+        state.start();
+        state.spawn_piece(typ);
+        state
+    }
+
+    #[test]
+    fn placements_on_an_empty_board() {
+        let counts = [
+            (PieceType::I, 17),
+            (PieceType::O, 9),
+            (PieceType::Z, 17),
+            (PieceType::S, 17),
+            (PieceType::T, 34),
+            (PieceType::J, 34),
+            (PieceType::L, 34),
+        ];
+        for (typ, count) in counts {
+            assert_eq!(placements(&mut empty(typ)).len(), count, "{:?}", typ);
+        }
+    }
+
+    #[test]
+    fn playing_a_placement_locks_it_there() {
+        for placement in placements(&mut empty(PieceType::Z)) {
+            let mut state = empty(PieceType::Z);
+            placement.play(&mut state);
+            for (x, y) in cells(&state, placement.piece) {
+                assert_eq!(
+                    state.cell(x as usize, y),
+                    Some(PieceType::Z),
+                    "{:?}",
+                    placement
+                );
+            }
+        }
+    }
+}
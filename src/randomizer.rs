@@ -0,0 +1,203 @@
+// Piece generators, see https://tetris.wiki/Random_Generator and
+// https://tetris.wiki/TGM_randomizer
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+
+use crate::state::PieceType;
+
+/// Source of the piece sequence. The game's seeded rng is passed in so that
+/// any randomizer stays reproducible from the seed alone
+pub trait Randomizer {
+    fn next(&mut self, rng: &mut StdRng) -> PieceType;
+}
+
+fn random_piece(rng: &mut StdRng) -> PieceType {
+    PieceType::from(rng.gen_range(0..7))
+}
+
+/// Shuffled bag containing `copies` of every piece plus `extras` uniformly
+/// random pieces, refilled once empty
+pub struct Bag {
+    copies: usize,
+    extras: usize,
+    pieces: Vec<PieceType>,
+}
+
+impl Bag {
+    pub fn new(copies: usize, extras: usize) -> Self {
+        Bag {
+            copies,
+            extras,
+            pieces: Vec::new(),
+        }
+    }
+
+    pub fn seven() -> Self {
+        Self::new(1, 0)
+    }
+
+    pub fn fourteen() -> Self {
+        Self::new(2, 0)
+    }
+
+    pub fn seven_plus_one() -> Self {
+        Self::new(1, 1)
+    }
+
+    fn refill(&mut self, rng: &mut StdRng) {
+        for _ in 0..self.copies {
+            self.pieces.extend((0..7).map(PieceType::from));
+        }
+        for _ in 0..self.extras {
+            self.pieces.push(random_piece(rng));
+        }
+        self.pieces.shuffle(rng);
+    }
+}
+
+impl Randomizer for Bag {
+    fn next(&mut self, rng: &mut StdRng) -> PieceType {
+        if self.pieces.is_empty() {
+            self.refill(rng);
+        }
+        self.pieces.pop().expect("Bag refilled with no pieces")
+    }
+}
+
+/// Every piece is picked independently
+pub struct Memoryless;
+
+impl Randomizer for Memoryless {
+    fn next(&mut self, rng: &mut StdRng) -> PieceType {
+        random_piece(rng)
+    }
+}
+
+/// TGM style randomizer, rerolls a piece up to `rolls` times while it is
+/// present in the history of the last 4 pieces
+pub struct History {
+    history: [PieceType; 4],
+    rolls: usize,
+    first: bool,
+}
+
+impl History {
+    pub fn tgm() -> Self {
+        History {
+            history: [PieceType::Z; 4],
+            rolls: 4,
+            first: true,
+        }
+    }
+}
+
+impl Randomizer for History {
+    fn next(&mut self, rng: &mut StdRng) -> PieceType {
+        let typ = if self.first {
+            // First piece is never S, Z or O
+            self.first = false;
+            *[PieceType::I, PieceType::J, PieceType::L, PieceType::T]
+                .choose(rng)
+                .expect("Choosing from empty list")
+        } else {
+            let mut typ = random_piece(rng);
+            for _ in 1..self.rolls {
+                if !self.history.contains(&typ) {
+                    break;
+                }
+                typ = random_piece(rng);
+            }
+            typ
+        };
+
+        self.history.rotate_right(1);
+        self.history[0] = typ;
+        typ
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RandomizerKind {
+    SevenBag,
+    FourteenBag,
+    SevenPlusOne,
+    Memoryless,
+    Tgm,
+}
+
+impl RandomizerKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bag7" => Some(RandomizerKind::SevenBag),
+            "bag14" => Some(RandomizerKind::FourteenBag),
+            "bag7+1" => Some(RandomizerKind::SevenPlusOne),
+            "random" => Some(RandomizerKind::Memoryless),
+            "tgm" => Some(RandomizerKind::Tgm),
+            _ => None,
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn Randomizer> {
+        match self {
+            RandomizerKind::SevenBag => Box::new(Bag::seven()),
+            RandomizerKind::FourteenBag => Box::new(Bag::fourteen()),
+            RandomizerKind::SevenPlusOne => Box::new(Bag::seven_plus_one()),
+            RandomizerKind::Memoryless => Box::new(Memoryless),
+            RandomizerKind::Tgm => Box::new(History::tgm()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn draw(randomizer: &mut dyn Randomizer, n: usize) -> Vec<PieceType> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..n).map(|_| randomizer.next(&mut rng)).collect()
+    }
+
+    fn count(pieces: &[PieceType], typ: PieceType) -> usize {
+        pieces.iter().filter(|p| **p == typ).count()
+    }
+
+    #[test]
+    fn bags_hold_every_piece() {
+        for (mut bag, size, copies) in [(Bag::seven(), 7, 1), (Bag::fourteen(), 14, 2)] {
+            for chunk in draw(&mut bag, size * 10).chunks(size) {
+                for typ in (0..7).map(PieceType::from) {
+                    assert_eq!(count(chunk, typ), copies, "{:?} in {:?}", typ, chunk);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn seven_plus_one_adds_a_piece() {
+        for chunk in draw(&mut Bag::seven_plus_one(), 80).chunks(8) {
+            for typ in (0..7).map(PieceType::from) {
+                assert!(count(chunk, typ) >= 1, "{:?} missing from {:?}", typ, chunk);
+            }
+        }
+    }
+
+    #[test]
+    fn tgm_history_avoids_repeats() {
+        // With this many rolls a repeat is all but impossible
+        let mut history = History {
+            rolls: 1000,
+            ..History::tgm()
+        };
+        let pieces = draw(&mut history, 200);
+        assert!(!matches!(
+            pieces[0],
+            PieceType::S | PieceType::Z | PieceType::O
+        ));
+        for (i, typ) in pieces.iter().enumerate() {
+            let recent = &pieces[i.saturating_sub(4)..i];
+            assert!(!recent.contains(typ), "{:?} repeated at {}", typ, i);
+        }
+    }
+}
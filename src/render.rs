@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    event::GameEvent,
+    state::{Piece, State},
+    TetrErr,
+};
+
+/// Something that can show a game, e.g. an SDL window or a terminal
+pub trait Renderer {
+    /// Draw the current frame. `status` is a line of text with the seed,
+    /// score and what the game is waiting for
+    fn draw(&mut self, state: &mut State, status: &str) -> Result<(), TetrErr>;
+
+    /// Called with every event the game emits, before the next `draw`
+    fn event(&mut self, _event: &GameEvent) {}
+
+    /// Show where a bot would put a piece, until called with `None`
+    fn hint(&mut self, _piece: Option<Piece>) {}
+
+    /// Show these stats from the next `draw` on, as pairs of a label and a
+    /// value
+    fn stats(&mut self, _stats: &[(&'static str, String)]) {}
+}
+
+/// Visible cells covered by `piece`, as `(x, y)` indexed like
+/// `State::board()`
+pub fn piece_cells(state: &State, piece: Piece) -> Vec<(usize, usize)> {
+    let buffer = state.dimensions().buffer;
+    state
+        .rotation()
+        .coords(piece.typ(), piece.rot())
+        .iter()
+        .filter_map(|(r, c)| {
+            let x = usize::try_from(piece.x() + *c as i32).ok()?;
+            Some((x, (piece.y() + r).checked_sub(buffer)?))
+        })
+        .collect()
+}
+
+/// How long cleared rows stay lit
+const FLASH: Duration = Duration::from_millis(150);
+
+/// Tracks recently cleared rows so they can be drawn lit up for a moment
+#[derive(Default)]
+pub struct ClearFlash {
+    rows: Vec<(usize, Instant)>,
+}
+
+impl ClearFlash {
+    pub fn event(&mut self, event: &GameEvent) {
+        if let GameEvent::Clear { rows } = event {
+            let now = Instant::now();
+            self.rows.extend(rows.iter().map(|y| (*y, now)));
+        }
+    }
+
+    /// Rows to draw lit, as visible rows indexed like `State::board()`
+    pub fn rows(&mut self, state: &State) -> Vec<usize> {
+        self.rows.retain(|(_, at)| at.elapsed() < FLASH);
+        let buffer = state.dimensions().buffer;
+        self.rows
+            .iter()
+            .filter_map(|(y, _)| y.checked_sub(buffer))
+            .collect()
+    }
+}
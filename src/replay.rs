@@ -0,0 +1,240 @@
+// Replays are TOML files holding the config the game was played with (seed
+// included) and every input, stamped with the frame it was applied on. As
+// the game is deterministic given those, playing the inputs back on the same
+// frames reproduces the game exactly
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use toml::{
+    Table,
+    Value::{self, Array, Boolean, Integer},
+};
+
+use crate::{
+    config::{parse_map, Config},
+    input::Key,
+    TetrErr,
+};
+
+// Version 3 stamps events with fixed ticks rather than loop iterations
+pub const REPLAY_VERSION: i64 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplayInput {
+    Key(Key, bool),
+    Pause,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayEvent {
+    pub frame: u64,
+    pub input: ReplayInput,
+}
+
+pub struct Replay {
+    config: Table,
+    events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    /// Start recording a game, `config` being the raw config table
+    pub fn new(mut config: Table, seed: u64) -> Self {
+        config.insert("seed".to_string(), Integer(seed as i64));
+        Replay {
+            config,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, frame: u64, input: ReplayInput) {
+        self.events.push(ReplayEvent { frame, input })
+    }
+
+    pub fn config(&self) -> Result<Config, TetrErr> {
+        parse_map(self.config.clone())
+    }
+
+    pub fn events(&self) -> &[ReplayEvent] {
+        &self.events
+    }
+
+    pub fn load(path: &Path) -> Result<Self, TetrErr> {
+        let tab: Table = fs::read_to_string(path)?.parse()?;
+
+        match tab.get("version") {
+            Some(Integer(REPLAY_VERSION)) => (),
+            Some(Integer(v)) => return Err(format!("Unsupported replay version {}", v).into()),
+            _ => return Err("Replay is missing version".to_string().into()),
+        }
+
+        let config = match tab.get("config") {
+            Some(Value::Table(t)) => t.clone(),
+            _ => return Err("Replay is missing config".to_string().into()),
+        };
+
+        let events = match tab.get("events") {
+            Some(Array(a)) => a
+                .iter()
+                .map(parse_event)
+                .collect::<Result<Vec<_>, TetrErr>>()?,
+            None => Vec::new(),
+            _ => return Err("Malformed replay events".to_string().into()),
+        };
+
+        Ok(Replay { config, events })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), TetrErr> {
+        let mut tab = Table::new();
+        tab.insert("version".to_string(), Integer(REPLAY_VERSION));
+        tab.insert("config".to_string(), Value::Table(self.config.clone()));
+        tab.insert(
+            "events".to_string(),
+            Array(
+                self.events
+                    .iter()
+                    .map(|e| {
+                        let (name, press) = match e.input {
+                            ReplayInput::Key(k, press) => (k.name(), press),
+                            ReplayInput::Pause => ("pause", true),
+                        };
+                        Array(vec![
+                            Integer(e.frame as i64),
+                            Value::String(name.to_string()),
+                            Boolean(press),
+                        ])
+                    })
+                    .collect(),
+            ),
+        );
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, tab.to_string())?;
+        Ok(())
+    }
+
+    /// Save under `~/.local/share/tetrs/replays`, named by seed and time
+    pub fn save_default(&self) -> Result<PathBuf, TetrErr> {
+        let mut path = homedir::my_home()?
+            .ok_or_else(|| TetrErr::Str("Unable to find home dir".to_string()))?;
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let seed = match self.config.get("seed") {
+            Some(Integer(s)) => *s as u64,
+            _ => 0,
+        };
+        path.push(format!(".local/share/tetrs/replays/{}-{}.toml", secs, seed));
+        self.save(&path)?;
+        Ok(path)
+    }
+}
+
+fn parse_event(val: &Value) -> Result<ReplayEvent, TetrErr> {
+    let malformed = || TetrErr::Str(format!("Malformed replay event {}", val));
+
+    match val.as_array().map(Vec::as_slice) {
+        Some([Integer(frame), Value::String(name), Boolean(press)]) => {
+            let input = match Key::from_name(name) {
+                Some(k) => ReplayInput::Key(k, *press),
+                None if name == "pause" => ReplayInput::Pause,
+                None => return Err(malformed()),
+            };
+            Ok(ReplayEvent {
+                frame: *frame as u64,
+                input,
+            })
+        }
+        _ => Err(malformed()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{
+        input::{KeyEvent, KeyState},
+        state::{CellView, State},
+    };
+
+    /// A few pieces moved, rotated and dropped, under gravity so that when
+    /// each input happens matters
+    fn recorded() -> Replay {
+        let mut config = Table::new();
+        config.insert("gravity".to_string(), Integer(5));
+        let mut replay = Replay::new(config, 42);
+        for i in 0..20 {
+            let frame = i * 30;
+            let (shift, rotate) = match i % 2 {
+                0 => (Key::Left, Key::RRot),
+                _ => (Key::Right, Key::LRot),
+            };
+            for (offset, key) in [(0, shift), (3, rotate), (20, Key::HDrop)] {
+                replay.push(frame + offset, ReplayInput::Key(key, true));
+                replay.push(frame + offset + 1, ReplayInput::Key(key, false));
+            }
+        }
+        replay
+    }
+
+    /// Feed the inputs on their frames, as the game does
+    fn play(replay: &Replay) -> (Vec<Vec<CellView>>, u64) {
+        let config = replay.config().unwrap();
+        let mut state = State::with_randomizer(config.seed().unwrap(), config.randomizer().build());
+        state.set_gravity(config.gravity());
+        state.start();
+        let mut keys = KeyState::new(config.handling());
+        let tick = Duration::from_secs_f64(1.0 / config.tick_rate() as f64);
+
+        let mut events = replay.events().iter().peekable();
+        for frame in 0..620 {
+            while let Some(event) = events.next_if(|e| e.frame <= frame) {
+                match event.input {
+                    ReplayInput::Key(key, press) => {
+                        keys.update(KeyEvent { key, press }, &mut state)
+                    }
+                    ReplayInput::Pause => state.toggle_pause(),
+                }
+            }
+            keys.tick(&mut state, tick);
+            state.tick();
+        }
+        (state.board(), state.scoring().score())
+    }
+
+    fn round_trip(replay: &Replay) -> Replay {
+        let path = std::env::temp_dir().join(format!("tetrs-replay-{}.toml", std::process::id()));
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        fs::remove_file(&path).unwrap();
+        loaded.unwrap()
+    }
+
+    #[test]
+    fn save_and_load() {
+        let replay = recorded();
+        let loaded = round_trip(&replay);
+        assert_eq!(loaded.config, replay.config);
+        assert_eq!(loaded.events, replay.events);
+    }
+
+    #[test]
+    fn playback_reproduces_the_game() {
+        let replay = recorded();
+        let (board, score) = play(&replay);
+        assert!(score > 0);
+        assert!(board
+            .iter()
+            .flatten()
+            .any(|c| matches!(c, CellView::Filled(_))));
+        assert_eq!(play(&round_trip(&replay)), (board, score));
+    }
+}
@@ -1,133 +1,161 @@
-use crate::state::{PieceType, RotationState};
+use crate::{
+    ruleset::{self, Ruleset},
+    state::{PieceType, RotationState},
+};
 
-//Everything in this file was taken from https://tetris.wiki/Super_Rotation_System
-
-pub fn i_coords(rot: RotationState) -> [(usize, usize); 4] {
-    match rot {
-        RotationState::None => [(1, 0), (1, 1), (1, 2), (1, 3)],
-        RotationState::Right => [(0, 2), (1, 2), (2, 2), (3, 2)],
-        RotationState::Flip => [(2, 0), (2, 1), (2, 2), (2, 3)],
-        RotationState::Left => [(0, 1), (1, 1), (2, 1), (3, 1)],
-    }
+/// Kick table used for 180 degree rotations, which SRS itself doesn't have
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kicks180 {
+    /// Only rotate in place
+    None,
+    /// Table from TETR.IO's SRS+, https://tetris.wiki/TETR.IO
+    Tetrio,
+    /// Table from Nullpomino's SRS, https://github.com/nullpomino/nullpomino
+    Nullpomino,
 }
 
-pub fn j_coords(rot: RotationState) -> [(usize, usize); 4] {
-    match rot {
-        RotationState::None => [(0, 0), (1, 0), (1, 1), (1, 2)],
-        RotationState::Right => [(0, 1), (0, 2), (1, 1), (2, 1)],
-        RotationState::Flip => [(1, 0), (1, 1), (1, 2), (2, 2)],
-        RotationState::Left => [(2, 0), (0, 1), (1, 1), (2, 1)],
+impl Kicks180 {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "none" => Some(Kicks180::None),
+            "tetrio" => Some(Kicks180::Tetrio),
+            "nullpomino" => Some(Kicks180::Nullpomino),
+            _ => None,
+        }
     }
 }
 
-pub fn l_coords(rot: RotationState) -> [(usize, usize); 4] {
-    match rot {
-        RotationState::None => [(1, 0), (1, 1), (1, 2), (0, 2)],
-        RotationState::Right => [(0, 1), (1, 1), (2, 1), (2, 2)],
-        RotationState::Flip => [(1, 0), (1, 1), (1, 2), (2, 0)],
-        RotationState::Left => [(0, 0), (0, 1), (1, 1), (2, 1)],
+// Same convention as RotationSystem::kicks, (x, y) with y pointing down
+pub fn get_180_wallkicks(
+    typ: PieceType,
+    rot: RotationState,
+    table: Kicks180,
+) -> &'static [(i32, i32)] {
+    match (table, typ) {
+        (Kicks180::None, _) | (_, PieceType::O) => &[(0, 0)],
+        (Kicks180::Tetrio, _) => match rot {
+            RotationState::None => &[(0, 0), (0, -1), (1, -1), (-1, -1), (1, 0), (-1, 0)],
+            RotationState::Flip => &[(0, 0), (0, 1), (-1, 1), (1, 1), (-1, 0), (1, 0)],
+            RotationState::Right => &[(0, 0), (1, 0), (1, -2), (1, -1), (0, -2), (0, -1)],
+            RotationState::Left => &[(0, 0), (-1, 0), (-1, -2), (-1, -1), (0, -2), (0, -1)],
+        },
+        (Kicks180::Nullpomino, PieceType::I) => match rot {
+            RotationState::None => &[(0, 0), (-1, 0), (-2, 0), (1, 0), (2, 0), (0, -1)],
+            RotationState::Flip => &[(0, 0), (1, 0), (2, 0), (-1, 0), (-2, 0), (0, 1)],
+            RotationState::Right => &[(0, 0), (0, -1), (0, -2), (0, 1), (0, 2), (-1, 0)],
+            RotationState::Left => &[(0, 0), (0, -1), (0, -2), (0, 1), (0, 2), (1, 0)],
+        },
+        (Kicks180::Nullpomino, _) => match rot {
+            RotationState::None => &[
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (1, -1),
+                (2, -1),
+                (-1, 0),
+                (-2, 0),
+                (-1, -1),
+                (-2, -1),
+                (0, 1),
+                (3, 0),
+                (-3, 0),
+            ],
+            RotationState::Flip => &[
+                (0, 0),
+                (-1, 0),
+                (-2, 0),
+                (-1, 1),
+                (-2, 1),
+                (1, 0),
+                (2, 0),
+                (1, 1),
+                (2, 1),
+                (0, -1),
+                (-3, 0),
+                (3, 0),
+            ],
+            RotationState::Right => &[
+                (0, 0),
+                (0, -1),
+                (0, -2),
+                (-1, -1),
+                (-1, -2),
+                (0, 1),
+                (0, 2),
+                (-1, 1),
+                (-1, 2),
+                (1, 0),
+                (0, -3),
+                (0, 3),
+            ],
+            RotationState::Left => &[
+                (0, 0),
+                (0, -1),
+                (0, -2),
+                (1, -1),
+                (1, -2),
+                (0, 1),
+                (0, 2),
+                (1, 1),
+                (1, 2),
+                (-1, 0),
+                (0, -3),
+                (0, 3),
+            ],
+        },
     }
 }
 
-pub fn o_coords(_: RotationState) -> [(usize, usize); 4] {
-    [(0, 1), (1, 1), (0, 2), (1, 2)]
-}
+/// Everything that differs between the rotation systems of different games.
+/// Kicks are `(x, y)` offsets with y pointing down, tried in order
+pub trait RotationSystem {
+    fn coords(&self, typ: PieceType, rot: RotationState) -> &[(usize, usize)];
 
-pub fn s_coords(rot: RotationState) -> [(usize, usize); 4] {
-    match rot {
-        RotationState::None => [(1, 0), (1, 1), (0, 1), (0, 2)],
-        RotationState::Right => [(0, 1), (1, 1), (1, 2), (2, 2)],
-        RotationState::Flip => [(1, 1), (1, 2), (2, 0), (2, 1)],
-        RotationState::Left => [(0, 0), (1, 0), (1, 1), (2, 1)],
-    }
+    fn kicks(&self, typ: PieceType, rot: RotationState, new_rot: RotationState) -> &[(i32, i32)];
+
+    /// Column of `typ`'s shapes that stops it kicking when it's the first
+    /// blocked, reading the rotated shape left to right and top to bottom.
+    /// TGM does this for J, L and T so they can't kick off the stack
+    fn centre_column(&self, typ: PieceType) -> Option<usize>;
+
+    /// Column and row of a newly spawned piece on a standard 10 wide board,
+    /// `(0, 0)` being the top left visible cell, along with its orientation
+    fn spawn(&self, typ: PieceType) -> (i32, i32, RotationState);
+
+    /// RGB colour pieces of this type are drawn with
+    fn colour(&self, typ: PieceType) -> (u8, u8, u8);
 }
 
-pub fn z_coords(rot: RotationState) -> [(usize, usize); 4] {
-    match rot {
-        RotationState::None => [(0, 0), (0, 1), (1, 1), (1, 2)],
-        RotationState::Right => [(1, 1), (0, 2), (1, 2), (2, 1)],
-        RotationState::Flip => [(1, 0), (1, 1), (2, 1), (2, 2)],
-        RotationState::Left => [(0, 1), (1, 0), (1, 1), (2, 0)],
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RotationSystemKind {
+    Srs,
+    SrsPlus,
+    Ars,
+    Nrs,
 }
 
-pub fn t_coords(rot: RotationState) -> [(usize, usize); 4] {
-    match rot {
-        RotationState::None => [(0, 1), (1, 0), (1, 1), (1, 2)],
-        RotationState::Right => [(0, 1), (1, 1), (1, 2), (2, 1)],
-        RotationState::Flip => [(1, 0), (1, 1), (1, 2), (2, 1)],
-        RotationState::Left => [(0, 1), (1, 0), (1, 1), (2, 1)],
+impl RotationSystemKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "srs" => Some(RotationSystemKind::Srs),
+            "srs+" => Some(RotationSystemKind::SrsPlus),
+            "ars" => Some(RotationSystemKind::Ars),
+            "nrs" => Some(RotationSystemKind::Nrs),
+            _ => None,
+        }
     }
-}
 
-pub fn get_wallkicks(
-    typ: PieceType,
-    rot: RotationState,
-    new_rot: RotationState,
-) -> [(i32, i32); 5] {
-    match typ {
-        PieceType::O => [(0, 0), (0, 0), (0, 0), (0, 0), (0, 0)],
-        PieceType::I => match (rot, new_rot) {
-            (RotationState::None, RotationState::Right) => {
-                [(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)]
-            }
-            (RotationState::Right, RotationState::None) => {
-                [(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)]
-            }
-            (RotationState::Right, RotationState::Flip) => {
-                [(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)]
-            }
-            (RotationState::Flip, RotationState::Right) => {
-                [(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)]
-            }
-            (RotationState::Flip, RotationState::Left) => {
-                [(0, 0), (2, 0), (-1, 0), (2, -1), (-1, 2)]
-            }
-            (RotationState::Left, RotationState::Flip) => {
-                [(0, 0), (-2, 0), (1, 0), (-2, 1), (1, -2)]
-            }
-            (RotationState::Left, RotationState::None) => {
-                [(0, 0), (1, 0), (-2, 0), (1, 2), (-2, -1)]
-            }
-            (RotationState::None, RotationState::Left) => {
-                [(0, 0), (-1, 0), (2, 0), (-1, -2), (2, 1)]
-            }
-            _ => panic!("Attempting to get wallkick for impossible rotation"),
-        },
-        _ => match (rot, new_rot) {
-            (RotationState::None, RotationState::Right) => {
-                [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]
-            }
-            (RotationState::Right, RotationState::None) => {
-                [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)]
-            }
-            (RotationState::Right, RotationState::Flip) => {
-                [(0, 0), (-1, 0), (1, 1), (0, -2), (1, -2)]
-            }
-            (RotationState::Flip, RotationState::Right) => {
-                [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)]
-            }
-            (RotationState::Flip, RotationState::Left) => [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
-            (RotationState::Left, RotationState::Flip) => {
-                [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]
-            }
-            (RotationState::Left, RotationState::None) => {
-                [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)]
-            }
-            (RotationState::None, RotationState::Left) => [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
-            _ => panic!("Attempting to get wallkick for impossible rotation"),
-        },
+    pub fn ruleset(&self) -> Ruleset {
+        let src = match self {
+            RotationSystemKind::Srs => ruleset::SRS,
+            RotationSystemKind::SrsPlus => ruleset::SRS_PLUS,
+            RotationSystemKind::Ars => ruleset::ARS,
+            RotationSystemKind::Nrs => ruleset::NRS,
+        };
+        Ruleset::parse(src).expect("Built-in ruleset is invalid")
     }
-}
 
-pub fn get_coords(typ: PieceType, rot: RotationState) -> [(usize, usize); 4] {
-    match typ {
-        PieceType::I => i_coords(rot),
-        PieceType::J => j_coords(rot),
-        PieceType::L => l_coords(rot),
-        PieceType::O => o_coords(rot),
-        PieceType::Z => z_coords(rot),
-        PieceType::S => s_coords(rot),
-        PieceType::T => t_coords(rot),
+    pub fn build(&self) -> Box<dyn RotationSystem> {
+        Box::new(self.ruleset())
     }
 }
@@ -0,0 +1,277 @@
+// Piece shapes, colours, spawn positions and kick tables loaded from TOML,
+// in the format described in the README. 180 rotations aren't part of a
+// ruleset, they use the kicks_180 table whatever the ruleset
+
+use toml::{
+    Table,
+    Value::{self, Array, Integer},
+};
+
+use crate::{
+    rotations::RotationSystem,
+    state::{Dimensions, PieceType, RotationState},
+    TetrErr,
+};
+
+pub const SRS: &str = include_str!("../config/rulesets/srs.toml");
+pub const SRS_PLUS: &str = include_str!("../config/rulesets/srs_plus.toml");
+pub const ARS: &str = include_str!("../config/rulesets/ars.toml");
+pub const NRS: &str = include_str!("../config/rulesets/nrs.toml");
+
+// Kick table keys, in the order they're stored
+const TRANSITIONS: [&str; 8] = ["0R", "R0", "R2", "2R", "2L", "L2", "L0", "0L"];
+
+#[derive(Clone, Debug)]
+struct PieceDef {
+    colour: (u8, u8, u8),
+    spawn: (i32, i32),
+    shapes: [Vec<(usize, usize)>; 4],
+    kicks: [Vec<(i32, i32)>; 8],
+    /// Column of the shapes that blocks kicks, for TGM's centre column rule
+    centre_column: Option<usize>,
+}
+
+/// A set of piece definitions, indexed by `PieceType`
+#[derive(Clone, Debug)]
+pub struct Ruleset {
+    pieces: Vec<PieceDef>,
+}
+
+fn rot_index(rot: RotationState) -> usize {
+    match rot {
+        RotationState::None => 0,
+        RotationState::Right => 1,
+        RotationState::Flip => 2,
+        RotationState::Left => 3,
+    }
+}
+
+fn transition_index(rot: RotationState, new_rot: RotationState) -> usize {
+    match (rot, new_rot) {
+        (RotationState::None, RotationState::Right) => 0,
+        (RotationState::Right, RotationState::None) => 1,
+        (RotationState::Right, RotationState::Flip) => 2,
+        (RotationState::Flip, RotationState::Right) => 3,
+        (RotationState::Flip, RotationState::Left) => 4,
+        (RotationState::Left, RotationState::Flip) => 5,
+        (RotationState::Left, RotationState::None) => 6,
+        (RotationState::None, RotationState::Left) => 7,
+        _ => panic!("Attempting to get wallkick for impossible rotation"),
+    }
+}
+
+fn invalid(key: &str, msg: &str) -> TetrErr {
+    TetrErr::Str(format!("Invalid ruleset {}: {}", key, msg))
+}
+
+fn parse_usize(val: Option<&Value>, key: &str, max: usize) -> Result<usize, TetrErr> {
+    match val {
+        Some(Integer(i)) if *i >= 0 && (*i as usize) < max => Ok(*i as usize),
+        _ => Err(invalid(key, &format!("expected an integer below {}", max))),
+    }
+}
+
+fn parse_colour(val: Option<&Value>, key: &str) -> Result<(u8, u8, u8), TetrErr> {
+    let err = || invalid(key, "expected [r, g, b] with values from 0 to 255");
+    match val.and_then(Value::as_array).map(Vec::as_slice) {
+        Some([r, g, b]) => {
+            let c = |v: &Value| v.as_integer().and_then(|i| u8::try_from(i).ok());
+            match (c(r), c(g), c(b)) {
+                (Some(r), Some(g), Some(b)) => Ok((r, g, b)),
+                _ => Err(err()),
+            }
+        }
+        _ => Err(err()),
+    }
+}
+
+/// Parse the rows of a shape, returning its cells and width
+fn parse_shape(val: &Value, key: &str) -> Result<(Vec<(usize, usize)>, usize), TetrErr> {
+    let rows = match val {
+        Array(rows) if !rows.is_empty() => rows,
+        _ => return Err(invalid(key, "expected a list of rows")),
+    };
+
+    let mut cells = Vec::new();
+    let mut width = None;
+    for (r, row) in rows.iter().enumerate() {
+        let row = match row {
+            Value::String(s) => s,
+            _ => return Err(invalid(key, "rows should be strings of \"#\" and \".\"")),
+        };
+        if *width.get_or_insert(row.len()) != row.len() {
+            return Err(invalid(key, "rows have different lengths"));
+        }
+        for (c, ch) in row.chars().enumerate() {
+            match ch {
+                '#' => cells.push((r, c)),
+                '.' => (),
+                _ => return Err(invalid(key, &format!("unexpected character '{}'", ch))),
+            }
+        }
+    }
+
+    if cells.is_empty() {
+        return Err(invalid(key, "shape has no cells"));
+    }
+    Ok((cells, width.unwrap_or(0)))
+}
+
+fn parse_kicks(tab: &Table, key: &str) -> Result<[Vec<(i32, i32)>; 8], TetrErr> {
+    let mut kicks: [Vec<(i32, i32)>; 8] = Default::default();
+    for (i, transition) in TRANSITIONS.iter().enumerate() {
+        let key = format!("{}.{}", key, transition);
+        let list = match tab.get(*transition) {
+            Some(Array(a)) if !a.is_empty() => a,
+            Some(_) => return Err(invalid(&key, "expected a non-empty list of [x, y]")),
+            None => return Err(invalid(&key, "missing")),
+        };
+        for kick in list {
+            match kick.as_array().map(Vec::as_slice) {
+                // Files use y up like the wiki, kicks are stored y down
+                Some([Integer(x), Integer(y)]) => kicks[i].push((*x as i32, -*y as i32)),
+                _ => return Err(invalid(&key, &format!("expected [x, y], found {}", kick))),
+            }
+        }
+    }
+    Ok(kicks)
+}
+
+fn parse_piece(tab: &Table, key: &str, kick_tables: &Table) -> Result<PieceDef, TetrErr> {
+    let colour = parse_colour(tab.get("colour"), &format!("{}.colour", key))?;
+
+    let shapes_key = format!("{}.shapes", key);
+    let shapes = match tab.get("shapes") {
+        Some(Array(a)) if a.len() == 4 => a,
+        _ => {
+            return Err(invalid(
+                &shapes_key,
+                "expected a shape for each of the 4 rotations",
+            ))
+        }
+    };
+    let mut cells: [Vec<(usize, usize)>; 4] = Default::default();
+    let mut width = 0;
+    for (i, shape) in shapes.iter().enumerate() {
+        let (shape, w) = parse_shape(shape, &format!("{}[{}]", shapes_key, i))?;
+        if i > 0 && shape.len() != cells[0].len() {
+            return Err(invalid(
+                &shapes_key,
+                "rotations have different numbers of cells",
+            ));
+        }
+        width = width.max(w);
+        cells[i] = shape;
+    }
+
+    // The whole shape has to fit in a standard width board when spawned
+    let spawn_key = format!("{}.spawn", key);
+    let max_x = Dimensions::default().width + 1 - width.min(Dimensions::default().width);
+    let spawn = match tab
+        .get("spawn")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+    {
+        Some([x, Integer(y)]) if i32::try_from(*y).is_ok() => {
+            (parse_usize(Some(x), &spawn_key, max_x)? as i32, *y as i32)
+        }
+        _ => return Err(invalid(&spawn_key, "expected [column, row]")),
+    };
+
+    let kicks = match tab.get("kicks") {
+        Some(Value::String(name)) => match kick_tables.get(name) {
+            Some(Value::Table(t)) => parse_kicks(t, &format!("kicks.{}", name))?,
+            _ => {
+                return Err(invalid(
+                    &format!("{}.kicks", key),
+                    &format!("no kick table named \"{}\"", name),
+                ))
+            }
+        },
+        None => Default::default(),
+        Some(_) => return Err(invalid(&format!("{}.kicks", key), "expected a table name")),
+    };
+
+    let centre_key = format!("{}.centre_column", key);
+    let centre_column = match tab.get("centre_column") {
+        Some(Value::Boolean(true)) if width % 2 == 1 => Some(width / 2),
+        Some(Value::Boolean(true)) => {
+            return Err(invalid(&centre_key, "shapes need a middle column"))
+        }
+        Some(Value::Boolean(false)) | None => None,
+        Some(_) => return Err(invalid(&centre_key, "expected true or false")),
+    };
+
+    Ok(PieceDef {
+        colour,
+        spawn,
+        shapes: cells,
+        kicks: kicks.map(|k| if k.is_empty() { vec![(0, 0)] } else { k }),
+        centre_column,
+    })
+}
+
+impl Ruleset {
+    pub fn from_table(tab: &Table) -> Result<Self, TetrErr> {
+        let pieces = match tab.get("pieces") {
+            Some(Value::Table(t)) => t,
+            _ => return Err(invalid("pieces", "missing")),
+        };
+        let kick_tables = match tab.get("kicks") {
+            Some(Value::Table(t)) => t,
+            None => &Table::new(),
+            Some(_) => return Err(invalid("kicks", "expected a table")),
+        };
+
+        if let Some(name) = pieces.keys().find(|k| PieceType::from_name(k).is_none()) {
+            return Err(invalid(
+                &format!("pieces.{}", name),
+                "pieces are named I, J, L, O, S, T and Z",
+            ));
+        }
+
+        let pieces = PieceType::ALL
+            .iter()
+            .map(|typ| {
+                let key = format!("pieces.{}", typ.name());
+                match pieces.get(typ.name()) {
+                    Some(Value::Table(t)) => parse_piece(t, &key, kick_tables),
+                    _ => Err(invalid(&key, "missing")),
+                }
+            })
+            .collect::<Result<Vec<_>, TetrErr>>()?;
+
+        Ok(Ruleset { pieces })
+    }
+
+    pub fn parse(s: &str) -> Result<Self, TetrErr> {
+        Self::from_table(&s.parse()?)
+    }
+
+    fn piece(&self, typ: PieceType) -> &PieceDef {
+        &self.pieces[typ as usize]
+    }
+}
+
+impl RotationSystem for Ruleset {
+    fn coords(&self, typ: PieceType, rot: RotationState) -> &[(usize, usize)] {
+        &self.piece(typ).shapes[rot_index(rot)]
+    }
+
+    fn kicks(&self, typ: PieceType, rot: RotationState, new_rot: RotationState) -> &[(i32, i32)] {
+        &self.piece(typ).kicks[transition_index(rot, new_rot)]
+    }
+
+    fn centre_column(&self, typ: PieceType) -> Option<usize> {
+        self.piece(typ).centre_column
+    }
+
+    fn spawn(&self, typ: PieceType) -> (i32, i32, RotationState) {
+        let (x, y) = self.piece(typ).spawn;
+        (x, y, RotationState::None)
+    }
+
+    fn colour(&self, typ: PieceType) -> (u8, u8, u8) {
+        self.piece(typ).colour
+    }
+}
@@ -0,0 +1,311 @@
+// Score values from https://tetris.wiki/Scoring, attack values follow the
+// guideline garbage table (https://tetris.wiki/Garbage)
+
+use crate::state::PieceType;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+/// Everything scoring needs to know about a single piece lock
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LockEvent {
+    pub typ: PieceType,
+    pub lines: u32,
+    pub spin: Spin,
+    pub perfect_clear: bool,
+    pub soft_drop_cells: u32,
+    pub hard_drop_cells: u32,
+}
+
+impl LockEvent {
+    /// Tetrises and line clearing spins continue a back-to-back chain
+    pub fn difficult(&self) -> bool {
+        self.lines == 4 || (self.lines > 0 && self.spin != Spin::None)
+    }
+}
+
+/// Points and attack earned by a single lock
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Award {
+    pub score: u64,
+    pub attack: u32,
+    pub back_to_back: bool,
+    pub combo: u32,
+}
+
+const COMBO_ATTACK: [u32; 13] = [0, 0, 1, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+
+fn clear_score(lines: u32, spin: Spin) -> u64 {
+    match (spin, lines) {
+        (Spin::None, 0) => 0,
+        (Spin::None, 1) => 100,
+        (Spin::None, 2) => 300,
+        (Spin::None, 3) => 500,
+        (Spin::None, _) => 800,
+        (Spin::Mini, 0) => 100,
+        (Spin::Mini, 1) => 200,
+        (Spin::Mini, _) => 400,
+        (Spin::Full, 0) => 400,
+        (Spin::Full, 1) => 800,
+        (Spin::Full, 2) => 1200,
+        (Spin::Full, _) => 1600,
+    }
+}
+
+fn clear_attack(lines: u32, spin: Spin) -> u32 {
+    match (spin, lines) {
+        (Spin::None, 0..=1) => 0,
+        (Spin::None, 2) => 1,
+        (Spin::None, 3) => 2,
+        (Spin::None, _) => 4,
+        (Spin::Mini, 0..=1) => 0,
+        (Spin::Mini, _) => 1,
+        (Spin::Full, l) => 2 * l,
+    }
+}
+
+fn perfect_clear_score(lines: u32, back_to_back: bool) -> u64 {
+    match lines {
+        1 => 800,
+        2 => 1200,
+        3 => 1800,
+        _ if back_to_back => 3200,
+        _ => 2000,
+    }
+}
+
+/// Score a lock given the chain state before it. `back_to_back` is whether
+/// the last line clear was difficult, `combo` how many locks in a row before
+/// this one cleared lines
+pub fn award(event: &LockEvent, back_to_back: bool, combo: u32, level: u32) -> Award {
+    let level = level.max(1) as u64;
+
+    if event.lines == 0 {
+        return Award {
+            score: clear_score(0, event.spin) * level
+                + event.soft_drop_cells as u64
+                + 2 * event.hard_drop_cells as u64,
+            ..Award::default()
+        };
+    }
+
+    let b2b = back_to_back && event.difficult();
+    let mut score = clear_score(event.lines, event.spin) * level;
+    let mut attack = clear_attack(event.lines, event.spin);
+    if b2b {
+        score = score * 3 / 2;
+        attack += 1;
+    }
+
+    score += 50 * combo as u64 * level;
+    attack += COMBO_ATTACK[(combo as usize).min(COMBO_ATTACK.len() - 1)];
+
+    if event.perfect_clear {
+        score += perfect_clear_score(event.lines, b2b) * level;
+        attack += 10;
+    }
+
+    Award {
+        score: score + event.soft_drop_cells as u64 + 2 * event.hard_drop_cells as u64,
+        attack,
+        back_to_back: b2b,
+        combo,
+    }
+}
+
+/// Running totals over every lock of a game
+#[derive(Clone, Debug, Default)]
+pub struct Scoring {
+    level: u32,
+    score: u64,
+    attack: u32,
+    lines: u32,
+    back_to_back: bool,
+    combo: Option<u32>,
+    events: Vec<LockEvent>,
+}
+
+impl Scoring {
+    pub fn new(level: u32) -> Self {
+        Scoring {
+            level,
+            ..Default::default()
+        }
+    }
+
+    /// Replay a list of lock events from the start of a game
+    pub fn from_events(level: u32, events: &[LockEvent]) -> Self {
+        let mut slf = Self::new(level);
+        for event in events {
+            slf.record(*event);
+        }
+        slf
+    }
+
+    pub fn record(&mut self, event: LockEvent) -> Award {
+        let combo = if event.lines > 0 {
+            self.combo.map_or(0, |c| c + 1)
+        } else {
+            0
+        };
+        let award = award(&event, self.back_to_back, combo, self.level);
+
+        self.score += award.score;
+        self.attack += award.attack;
+        self.lines += event.lines;
+        if event.lines > 0 {
+            self.back_to_back = event.difficult();
+            self.combo = Some(combo);
+        } else {
+            self.combo = None;
+        }
+        self.events.push(event);
+
+        award
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn set_level(&mut self, level: u32) {
+        self.level = level;
+    }
+
+    pub fn score(&self) -> u64 {
+        self.score
+    }
+
+    pub fn attack(&self) -> u32 {
+        self.attack
+    }
+
+    pub fn lines(&self) -> u32 {
+        self.lines
+    }
+
+    pub fn back_to_back(&self) -> bool {
+        self.back_to_back
+    }
+
+    /// Current combo, `None` if the last lock didn't clear any lines
+    pub fn combo(&self) -> Option<u32> {
+        self.combo
+    }
+
+    pub fn events(&self) -> &[LockEvent] {
+        &self.events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock(typ: PieceType, lines: u32, spin: Spin) -> LockEvent {
+        LockEvent {
+            typ,
+            lines,
+            spin,
+            perfect_clear: false,
+            soft_drop_cells: 0,
+            hard_drop_cells: 0,
+        }
+    }
+
+    #[test]
+    fn clears_and_spins() {
+        let table = [
+            (PieceType::I, 1, Spin::None, 100, 0),
+            (PieceType::I, 2, Spin::None, 300, 1),
+            (PieceType::I, 3, Spin::None, 500, 2),
+            (PieceType::I, 4, Spin::None, 800, 4),
+            (PieceType::T, 0, Spin::Mini, 100, 0),
+            (PieceType::T, 1, Spin::Mini, 200, 0),
+            (PieceType::T, 2, Spin::Mini, 400, 1),
+            (PieceType::T, 0, Spin::Full, 400, 0),
+            (PieceType::T, 1, Spin::Full, 800, 2),
+            (PieceType::T, 2, Spin::Full, 1200, 4),
+            (PieceType::T, 3, Spin::Full, 1600, 6),
+        ];
+        for (typ, lines, spin, score, attack) in table {
+            let award = award(&lock(typ, lines, spin), false, 0, 1);
+            assert_eq!(
+                (award.score, award.attack),
+                (score, attack),
+                "{} lines, {:?}",
+                lines,
+                spin
+            );
+        }
+    }
+
+    #[test]
+    fn scales_with_level_and_counts_drops() {
+        let mut event = lock(PieceType::I, 2, Spin::None);
+        event.soft_drop_cells = 3;
+        event.hard_drop_cells = 5;
+        assert_eq!(award(&event, false, 0, 4).score, 300 * 4 + 3 + 2 * 5);
+    }
+
+    #[test]
+    fn back_to_back() {
+        let tetris = award(&lock(PieceType::I, 4, Spin::None), true, 0, 1);
+        assert!(tetris.back_to_back);
+        assert_eq!((tetris.score, tetris.attack), (1200, 5));
+
+        let tsd = award(&lock(PieceType::T, 2, Spin::Full), true, 0, 1);
+        assert!(tsd.back_to_back);
+        assert_eq!((tsd.score, tsd.attack), (1800, 5));
+
+        // An easy clear doesn't get the bonus
+        let single = award(&lock(PieceType::I, 1, Spin::None), true, 0, 1);
+        assert!(!single.back_to_back);
+        assert_eq!((single.score, single.attack), (100, 0));
+    }
+
+    #[test]
+    fn combo() {
+        let single = lock(PieceType::I, 1, Spin::None);
+        let award3 = award(&single, false, 3, 1);
+        assert_eq!((award3.score, award3.attack, award3.combo), (250, 1, 3));
+        // Combo attack stops growing at the end of the table
+        assert_eq!(award(&single, false, 20, 1).attack, 5);
+    }
+
+    #[test]
+    fn perfect_clear() {
+        let mut event = lock(PieceType::I, 4, Spin::None);
+        event.perfect_clear = true;
+        let pc = award(&event, false, 0, 1);
+        assert_eq!((pc.score, pc.attack), (800 + 2000, 14));
+        let b2b_pc = award(&event, true, 0, 1);
+        assert_eq!((b2b_pc.score, b2b_pc.attack), (1200 + 3200, 15));
+
+        let mut event = lock(PieceType::O, 2, Spin::None);
+        event.perfect_clear = true;
+        assert_eq!(award(&event, false, 0, 1).score, 300 + 1200);
+    }
+
+    #[test]
+    fn record_keeps_chains() {
+        let mut scoring = Scoring::new(1);
+        scoring.record(lock(PieceType::I, 4, Spin::None));
+        assert_eq!(scoring.combo(), Some(0));
+        let second = scoring.record(lock(PieceType::I, 4, Spin::None));
+        assert!(second.back_to_back);
+        assert_eq!(second.combo, 1);
+
+        scoring.record(lock(PieceType::O, 0, Spin::None));
+        assert_eq!(scoring.combo(), None);
+        assert!(scoring.back_to_back());
+        scoring.record(lock(PieceType::I, 1, Spin::None));
+        assert!(!scoring.back_to_back());
+        assert_eq!(scoring.lines(), 9);
+        assert_eq!(scoring.attack(), 4 + 5);
+    }
+}
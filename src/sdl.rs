@@ -0,0 +1,376 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use sdl2::{
+    controller::{Axis, GameController},
+    event::Event,
+    keyboard::Keycode,
+    pixels::Color,
+    rect::Rect,
+    render::Canvas,
+    sys::SDL_RendererFlags,
+    video::Window,
+    EventPump, GameControllerSubsystem, TimerSubsystem,
+};
+
+use crate::{
+    config::{sdl_keys, Binding, KeyConfig},
+    event::GameEvent,
+    font::{draw_text, GLYPH_HEIGHT},
+    game::{self, load_config, Frontend, Input},
+    input::Action,
+    render::{piece_cells, ClearFlash, Renderer},
+    replay::Replay,
+    rotations::RotationSystem,
+    state::{CellView, Piece, PieceType, RotationState, State, Status},
+    TetrErr,
+};
+
+fn piece_colour(rotation: &dyn RotationSystem, typ: PieceType) -> Color {
+    let (r, g, b) = rotation.colour(typ);
+    Color::RGB(r, g, b)
+}
+
+fn cell_colour(rotation: &dyn RotationSystem, cell: CellView) -> Color {
+    match cell {
+        CellView::Empty => Color::BLACK,
+        CellView::Ghost => Color::WHITE,
+        CellView::Active(t) | CellView::Filled(t) => piece_colour(rotation, t),
+    }
+}
+
+// Space the board is drawn in, below the hold and next pieces
+const BOARD_WIDTH: u32 = 500;
+const BOARD_HEIGHT: u32 = 1000;
+
+// Column right of the board for the previews after the first, with the
+// stats below them
+const PREVIEW_WIDTH: u32 = 150;
+const WINDOW_WIDTH: u32 = BOARD_WIDTH + PREVIEW_WIDTH;
+
+// Where the stats start, below the room the most previews take up
+const STATS_Y: i32 = 760;
+const TEXT_SCALE: u32 = 3;
+
+/// Draw `typ` in its spawn orientation, the top left of its shape at `(x, y)`
+fn draw_piece(
+    canvas: &mut Canvas<Window>,
+    rotation: &dyn RotationSystem,
+    typ: PieceType,
+    color: Color,
+    (x, y): (i32, i32),
+    size: u32,
+) -> Result<(), TetrErr> {
+    canvas.set_draw_color(color);
+    for (r, c) in rotation.coords(typ, RotationState::None) {
+        canvas.fill_rect(Rect::new(
+            x + *c as i32 * size as i32,
+            y + *r as i32 * size as i32,
+            size,
+            size,
+        ))?;
+    }
+    Ok(())
+}
+
+/// Each stat's label with its value on the line below
+fn draw_stats(canvas: &mut Canvas<Window>, stats: &[(&str, String)]) -> Result<(), TetrErr> {
+    let line = ((GLYPH_HEIGHT + 2) * TEXT_SCALE) as i32;
+    let x = BOARD_WIDTH as i32 + 10;
+    for (i, (label, value)) in stats.iter().enumerate() {
+        let y = STATS_Y + i as i32 * 2 * line;
+        draw_text(canvas, label, (x, y), TEXT_SCALE, Color::RGB(64, 64, 64))?;
+        draw_text(canvas, value, (x, y + line), TEXT_SCALE, Color::BLACK)?;
+    }
+    Ok(())
+}
+
+fn draw_field(
+    state: &mut State,
+    canvas: &mut Canvas<Window>,
+    previews: usize,
+    flash: &[usize],
+    hint: Option<Piece>,
+) -> Result<(), TetrErr> {
+    canvas.set_draw_color(Color::BLACK);
+    canvas.clear();
+
+    canvas.set_draw_color(Color::GRAY);
+    canvas.fill_rect(Rect::new(0, 0, WINDOW_WIDTH, 150))?;
+    canvas.fill_rect(Rect::new(
+        BOARD_WIDTH as i32,
+        150,
+        PREVIEW_WIDTH,
+        BOARD_HEIGHT,
+    ))?;
+
+    let next = state.preview(previews);
+    let rotation = state.rotation();
+    if let Some(hold) = state.get_hold_piece() {
+        let color = if state.can_hold() {
+            piece_colour(rotation, hold)
+        } else {
+            Color::BLACK
+        };
+        draw_piece(canvas, rotation, hold, color, (0, 0), 50)?;
+    }
+    for (i, typ) in next.into_iter().enumerate() {
+        let color = piece_colour(rotation, typ);
+        match i {
+            0 => draw_piece(canvas, rotation, typ, color, (300, 0), 50)?,
+            _ => draw_piece(
+                canvas,
+                rotation,
+                typ,
+                color,
+                (BOARD_WIDTH as i32 + 25, 150 + (i as i32 - 1) * 100),
+                25,
+            )?,
+        }
+    }
+
+    if state.status() == Status::Paused {
+        return Ok(());
+    }
+
+    let dims = state.dimensions();
+    let size = (BOARD_WIDTH / dims.width as u32)
+        .min(BOARD_HEIGHT / dims.height as u32)
+        .max(1);
+    let left = (BOARD_WIDTH - size * dims.width as u32) as i32 / 2;
+    // Hints are drawn dimmed wherever nothing else is
+    let (hint_cells, hint_color) = match hint {
+        Some(p) => {
+            let (r, g, b) = state.rotation().colour(p.typ());
+            (piece_cells(state, p), Color::RGB(r / 2, g / 2, b / 2))
+        }
+        None => (vec![], Color::BLACK),
+    };
+    for (y, row) in state.board().into_iter().enumerate() {
+        for (x, cell) in row.into_iter().enumerate() {
+            let color = match (state.status(), cell) {
+                _ if flash.contains(&y) => Color::WHITE,
+                (_, CellView::Empty | CellView::Ghost) if hint_cells.contains(&(x, y)) => {
+                    hint_color
+                }
+                (Status::Over(_) | Status::Finished, CellView::Filled(_)) => Color::GRAY,
+                (_, cell) => cell_colour(state.rotation(), cell),
+            };
+            canvas.set_draw_color(color);
+            canvas.fill_rect(Rect::new(
+                left + x as i32 * size as i32,
+                150 + y as i32 * size as i32,
+                size,
+                size,
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Controller axis travel needed for an axis binding to count as pressed
+const AXIS_THRESHOLD: i16 = i16::MAX / 2;
+
+/// Tracks which axis directions are pushed past the threshold, so that axis
+/// motion can be turned into press and release events like buttons
+#[derive(Default)]
+struct AxisState {
+    pressed: Vec<(Axis, bool)>,
+}
+
+impl AxisState {
+    /// Bindings that changed state with this axis motion, along with
+    /// whether they're now pressed
+    fn update(&mut self, axis: Axis, value: i16) -> Vec<(Binding, bool)> {
+        let mut changes = Vec::new();
+        for positive in [true, false] {
+            let pressed = if positive {
+                value > AXIS_THRESHOLD
+            } else {
+                value < -AXIS_THRESHOLD
+            };
+            let was_pressed = self.pressed.contains(&(axis, positive));
+            if pressed && !was_pressed {
+                self.pressed.push((axis, positive));
+                changes.push((Binding::Axis(axis, positive), true));
+            } else if !pressed && was_pressed {
+                self.pressed.retain(|p| *p != (axis, positive));
+                changes.push((Binding::Axis(axis, positive), false));
+            }
+        }
+        changes
+    }
+}
+
+fn binding_name(bindings: &[Binding]) -> String {
+    match bindings.first() {
+        Some(b) => b.name(),
+        None => "(unbound)".to_string(),
+    }
+}
+
+struct SdlFrontend {
+    canvas: Canvas<Window>,
+    event_pump: EventPump,
+    timer: TimerSubsystem,
+    controller_subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>,
+    axes: AxisState,
+    keys: KeyConfig<Binding>,
+    previews: usize,
+    title: String,
+    flash: ClearFlash,
+    hint: Option<Piece>,
+    stats: Vec<(&'static str, String)>,
+}
+
+impl Renderer for SdlFrontend {
+    fn draw(&mut self, state: &mut State, status: &str) -> Result<(), TetrErr> {
+        if status != self.title {
+            self.canvas
+                .window_mut()
+                .set_title(status)
+                .map_err(|e| e.to_string())?;
+            self.title = status.to_string();
+        }
+
+        self.canvas.set_draw_color(Color::RGB(0, 255, 255));
+        self.canvas.clear();
+
+        let flash = self.flash.rows(state);
+        draw_field(state, &mut self.canvas, self.previews, &flash, self.hint)?;
+        draw_stats(&mut self.canvas, &self.stats)?;
+
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn event(&mut self, event: &GameEvent) {
+        self.flash.event(event);
+    }
+
+    fn hint(&mut self, piece: Option<Piece>) {
+        self.hint = piece;
+    }
+
+    fn stats(&mut self, stats: &[(&'static str, String)]) {
+        self.stats = stats.to_vec();
+    }
+}
+
+impl Frontend for SdlFrontend {
+    fn poll(&mut self) -> Result<Vec<(Instant, Input)>, TetrErr> {
+        // Event timestamps are in SDL ticks, ms since initialisation
+        let now = Instant::now();
+        let ticks = self.timer.ticks();
+
+        let mut inputs = Vec::new();
+        for event in self.event_pump.poll_iter() {
+            let time = now
+                .checked_sub(Duration::from_millis(
+                    ticks.saturating_sub(event.get_timestamp()) as u64,
+                ))
+                .unwrap_or(now);
+            let changes = match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => {
+                    inputs.push((time, Input::Quit));
+                    vec![]
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    match self.controller_subsystem.open(which) {
+                        Ok(c) => self.controllers.push(c),
+                        Err(e) => eprintln!("Unable to open controller: {}", e),
+                    }
+                    vec![]
+                }
+                Event::KeyDown {
+                    keycode: Some(kc),
+                    repeat: false,
+                    ..
+                } => vec![(Binding::Key(kc), true)],
+                Event::KeyUp {
+                    keycode: Some(kc),
+                    repeat: false,
+                    ..
+                } => vec![(Binding::Key(kc), false)],
+                Event::ControllerButtonDown { button, .. } => {
+                    vec![(Binding::Button(button), true)]
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    vec![(Binding::Button(button), false)]
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => self.axes.update(axis, value),
+                _ => vec![],
+            };
+
+            inputs.extend(changes.into_iter().filter_map(|(binding, press)| {
+                self.keys
+                    .action(binding)
+                    .map(|action| (time, Input::Action(action, press)))
+            }));
+        }
+        Ok(inputs)
+    }
+
+    fn vsync(&self) -> bool {
+        self.canvas.info().flags & SDL_RendererFlags::SDL_RENDERER_PRESENTVSYNC as u32 != 0
+    }
+
+    fn binding_name(&self, action: Action) -> String {
+        binding_name(self.keys.bindings(action))
+    }
+}
+
+pub fn run() -> Result<(), TetrErr> {
+    run_game(None)
+}
+
+/// Play back a replay file in the window, only quitting is accepted as input
+pub fn play_replay(path: &Path) -> Result<(), TetrErr> {
+    run_game(Some(Replay::load(path)?))
+}
+
+fn run_game(playback: Option<Replay>) -> Result<(), TetrErr> {
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+
+    let (table, config) = load_config(playback.as_ref())?;
+    let keys = sdl_keys(config.keys())?;
+
+    let window = video_subsystem
+        .window("tet.rs", WINDOW_WIDTH, 150 + BOARD_HEIGHT)
+        .position_centered()
+        .always_on_top()
+        .build()
+        .expect("Unable to initialize window");
+
+    let mut canvas = window.into_canvas();
+    if config.fps().is_none() {
+        canvas = canvas.present_vsync();
+    }
+    let canvas = canvas.build().expect("Unable to create canvas");
+
+    let mut frontend = SdlFrontend {
+        canvas,
+        event_pump: sdl_context.event_pump()?,
+        timer: sdl_context.timer()?,
+        controller_subsystem: sdl_context.game_controller()?,
+        controllers: Vec::new(),
+        axes: AxisState::default(),
+        keys,
+        previews: config.previews(),
+        title: String::new(),
+        flash: ClearFlash::default(),
+        hint: None,
+        stats: Vec::new(),
+    };
+
+    game::run(&mut frontend, &table, &config, playback.as_ref())
+}
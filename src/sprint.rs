@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+/// Lines between split times
+pub const SPLIT_LINES: u32 = 10;
+
+/// Timer for a line clearing race. Time only passes through `advance`, so
+/// the frontend decides what counts as a running clock (e.g. not paused)
+#[derive(Clone, Debug)]
+pub struct Sprint {
+    target: u32,
+    elapsed: Duration,
+    running: bool,
+    splits: Vec<Duration>,
+    finished: Option<Duration>,
+}
+
+impl Sprint {
+    pub fn new(target: u32) -> Self {
+        Sprint {
+            target,
+            elapsed: Duration::ZERO,
+            running: false,
+            splits: Vec::new(),
+            finished: None,
+        }
+    }
+
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    /// Start the clock, called on the first input of a run
+    pub fn start(&mut self) {
+        if self.finished.is_none() {
+            self.running = true;
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.running
+    }
+
+    /// Add `dt` to the clock and record any splits or the finish reached
+    /// with `lines` cleared
+    pub fn advance(&mut self, dt: Duration, lines: u32) {
+        if !self.running {
+            return;
+        }
+
+        self.elapsed += dt;
+        while (self.splits.len() as u32 + 1) * SPLIT_LINES <= lines.min(self.target) {
+            self.splits.push(self.elapsed);
+        }
+        if lines >= self.target {
+            self.running = false;
+            self.finished = Some(self.elapsed);
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Time at which every multiple of `SPLIT_LINES` was reached
+    pub fn splits(&self) -> &[Duration] {
+        &self.splits
+    }
+
+    /// Final time, once `target` lines were cleared
+    pub fn finished(&self) -> Option<Duration> {
+        self.finished
+    }
+}
+
+/// Format as `m:ss.mmm`
+pub fn format_time(d: Duration) -> String {
+    format!(
+        "{}:{:02}.{:03}",
+        d.as_secs() / 60,
+        d.as_secs() % 60,
+        d.subsec_millis()
+    )
+}
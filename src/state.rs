@@ -1,14 +1,35 @@
-use rand::{seq::SliceRandom, thread_rng};
-use sdl2::pixels::Color;
+use std::collections::VecDeque;
 
-use crate::rotations::{get_coords, get_wallkicks};
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
 
-pub const FIELD_WIDTH: usize = 12;
-pub const FIELD_VIS_WIDTH: usize = 10;
-pub const FIELD_HEIGHT: usize = 22;
-pub const FIELD_VIS_HEIGHT: usize = 20;
+use crate::{
+    event::GameEvent,
+    gravity::{Gravity, GravityMode},
+    randomizer::{Bag, Randomizer},
+    rotations::{get_180_wallkicks, Kicks180, RotationSystem, RotationSystemKind},
+    scoring::{LockEvent, Scoring, Spin},
+};
 
-#[derive(Clone, Copy)]
+/// Size of the board, `buffer` being the number of hidden rows above the
+/// visible ones that pieces spawn into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dimensions {
+    pub width: usize,
+    pub height: usize,
+    pub buffer: usize,
+}
+
+impl Default for Dimensions {
+    fn default() -> Self {
+        Dimensions {
+            width: 10,
+            height: 20,
+            buffer: 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum RotationState {
     None,
     Right,
@@ -26,6 +47,10 @@ impl RotationState {
         }
     }
 
+    pub fn flip(&self) -> Self {
+        self.right().right()
+    }
+
     pub fn left(&self) -> Self {
         match self {
             RotationState::None => RotationState::Left,
@@ -36,7 +61,7 @@ impl RotationState {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum PieceType {
     I,
     J,
@@ -62,31 +87,128 @@ impl From<u32> for PieceType {
     }
 }
 
-impl From<PieceType> for Color {
-    fn from(val: PieceType) -> Self {
-        match val {
-            PieceType::I => Color::RGB(0, 255, 255),
-            PieceType::J => Color::BLUE,
-            PieceType::L => Color::RGB(255, 128, 0),
-            PieceType::O => Color::YELLOW,
-            PieceType::Z => Color::RED,
-            PieceType::S => Color::GREEN,
-            PieceType::T => Color::RGB(127, 0, 255),
+impl PieceType {
+    pub const ALL: [PieceType; 7] = [
+        PieceType::I,
+        PieceType::J,
+        PieceType::L,
+        PieceType::O,
+        PieceType::Z,
+        PieceType::S,
+        PieceType::T,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PieceType::I => "I",
+            PieceType::J => "J",
+            PieceType::L => "L",
+            PieceType::O => "O",
+            PieceType::Z => "Z",
+            PieceType::S => "S",
+            PieceType::T => "T",
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        PieceType::ALL.into_iter().find(|t| t.name() == name)
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Piece {
-    x: usize,
+    x: i32,
     y: usize,
     typ: PieceType,
     rot: RotationState,
 }
 
+impl Piece {
+    /// `y` is counted from the top of the hidden buffer, like `Piece::y`
+    pub fn new(typ: PieceType, rot: RotationState, x: i32, y: usize) -> Self {
+        Piece { x, y, typ, rot }
+    }
+
+    /// Column of the left of the piece's shape, which can hang over the
+    /// left wall
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    /// Row of the top of the piece's shape, counted from the top of the
+    /// hidden buffer
+    pub fn y(&self) -> usize {
+        self.y
+    }
+
+    pub fn typ(&self) -> PieceType {
+        self.typ
+    }
+
+    pub fn rot(&self) -> RotationState {
+        self.rot
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameOverReason {
+    /// A new piece couldn't spawn because it overlapped the stack
+    BlockOut,
+    /// A piece locked entirely inside the hidden rows above the field
+    LockOut,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Ready,
+    Playing,
+    Paused,
+    /// The line goal set with `State::set_line_goal` was reached
+    Finished,
+    Over(GameOverReason),
+}
+
+/// What the hold key does, see https://tetris.wiki/Hold_piece. A piece taken
+/// out of hold always spawns like a new piece, at the spawn position in its
+/// spawn orientation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HoldMode {
+    /// Hold does nothing
+    Disabled,
+    /// Hold once, then not again until the next piece spawns after a lock
+    Guideline,
+    /// Hold as often as you like, for practice
+    Infinite,
+    /// Swap the active piece with the next one in the queue instead of a
+    /// hold slot, once per piece like `Guideline`
+    SwapNext,
+}
+
+impl HoldMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(HoldMode::Disabled),
+            "guideline" => Some(HoldMode::Guideline),
+            "infinite" => Some(HoldMode::Infinite),
+            "swap_next" => Some(HoldMode::SwapNext),
+            _ => None,
+        }
+    }
+}
+
+/// What occupies a single visible cell of the board, independent of how a
+/// frontend chooses to draw it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellView {
+    Empty,
+    Ghost,
+    Active(PieceType),
+    Filled(PieceType),
+}
+
 #[derive(Clone, Copy)]
 pub struct Ghost {
-    x: usize,
+    x: i32,
     y: usize,
 }
 
@@ -95,186 +217,487 @@ pub struct Cell {
     typ: Option<PieceType>,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Row {
-    cells: [Cell; 12],
-}
-
-#[derive(Clone, Copy)]
-pub struct Bag {
-    index: usize,
-    pieces: [PieceType; 7],
+    cells: Vec<Cell>,
 }
 
-impl Bag {
-    fn pop(&mut self) -> Option<PieceType> {
-        if self.index < 7 {
-            self.index += 1;
-            Some(self.pieces[self.index - 1])
-        } else {
-            None
-        }
-    }
-
-    fn peek(&self) -> Option<PieceType> {
-        if self.index < 7 {
-            Some(self.pieces[self.index])
-        } else {
-            None
+impl Row {
+    fn empty(width: usize) -> Self {
+        Row {
+            cells: vec![Cell { typ: None }; width],
         }
     }
 }
 
-fn generate_bag() -> Bag {
-    let mut arr = [0, 1, 2, 3, 4, 5, 6];
-    arr.shuffle(&mut thread_rng());
-    Bag {
-        pieces: arr.map(PieceType::from),
-        index: 0,
-    }
-}
-
 // #[derive(Clone, Copy)]
 pub struct State {
-    rows: [Row; 22],
+    dims: Dimensions,
+    rows: Vec<Row>,
     piece: Piece,
     ghost: Ghost,
     hold: Option<PieceType>,
+    hold_mode: HoldMode,
     can_hold: bool,
-    bag: Bag,
-    next_bag: Bag,
+    status: Status,
+    gravity: Gravity,
+    fall: f64,
+    /// Time spent on the ground, in 60 Hz frames
+    lock_frames: f64,
+    /// 60 Hz frames a call to `tick` advances by
+    frames_per_tick: f64,
+    lock_resets: u32,
+    /// A move on the ground was refused a reset, so the piece locks as soon
+    /// as it's on the ground
+    resets_exhausted: bool,
+    lowest_y: usize,
+    scoring: Scoring,
+    line_goal: Option<u32>,
+    kicks_180: Kicks180,
+    rotation: Box<dyn RotationSystem>,
+    spawns: u64,
+    last_kick: Option<usize>,
+    soft_drop_cells: u32,
+    events: Vec<GameEvent>,
+    queue: VecDeque<PieceType>,
+    randomizer: Box<dyn Randomizer>,
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl State {
     pub fn new() -> Self {
+        Self::with_seed(thread_rng().gen())
+    }
+
+    /// Create a game whose entire piece sequence is determined by `seed`
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_randomizer(seed, Box::new(Bag::seven()))
+    }
+
+    pub fn with_randomizer(seed: u64, randomizer: Box<dyn Randomizer>) -> Self {
         let mut slf = State {
             piece: Piece {
-                x: 5,
+                x: 0,
                 y: 0,
                 typ: PieceType::I,
                 rot: RotationState::None,
             },
             can_hold: true,
             hold: None,
+            hold_mode: HoldMode::Guideline,
             ghost: Ghost { x: 0, y: 0 },
-            rows: [Row {
-                cells: [Cell { typ: None }; 12],
-            }; 22],
-            bag: generate_bag(),
-            next_bag: generate_bag(),
+            dims: Dimensions::default(),
+            rows: vec![
+                Row::empty(Dimensions::default().width);
+                Dimensions::default().height + Dimensions::default().buffer
+            ],
+            status: Status::Ready,
+            gravity: Gravity::off(),
+            fall: 0.0,
+            lock_frames: 0.0,
+            frames_per_tick: 1.0,
+            lock_resets: 0,
+            resets_exhausted: false,
+            lowest_y: 0,
+            scoring: Scoring::new(1),
+            line_goal: None,
+            kicks_180: Kicks180::Tetrio,
+            rotation: RotationSystemKind::Srs.build(),
+            spawns: 0,
+            last_kick: None,
+            soft_drop_cells: 0,
+            events: Vec::new(),
+            queue: VecDeque::new(),
+            randomizer,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
         };
         slf.next_piece();
         slf
     }
 
-    pub fn get_next_piece(&mut self) -> PieceType {
-        match self.bag.peek() {
-            Some(p) => p,
-            None => {
-                self.bag = self.next_bag;
-                self.next_bag = generate_bag();
-                self.bag.peek().expect("next_bag is empty")
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    fn playing(&self) -> bool {
+        self.status == Status::Playing
+    }
+
+    /// Leave the ready state, actions are ignored until this is called
+    pub fn start(&mut self) {
+        if self.status == Status::Ready {
+            self.status = Status::Playing;
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        match self.status {
+            Status::Playing => self.status = Status::Paused,
+            Status::Paused => self.status = Status::Playing,
+            _ => (),
+        }
+    }
+
+    pub fn gravity(&self) -> Gravity {
+        self.gravity
+    }
+
+    pub fn set_gravity(&mut self, gravity: Gravity) {
+        self.gravity = gravity;
+        if let GravityMode::Level(l) = gravity.mode() {
+            self.scoring.set_level(l);
+        }
+    }
+
+    /// Take the events emitted since the last call. They pile up until
+    /// taken, so anything running the game for long should call this
+    /// regularly
+    pub fn take_events(&mut self) -> Vec<GameEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn top_out(&mut self, reason: GameOverReason) {
+        self.status = Status::Over(reason);
+        self.events.push(GameEvent::TopOut(reason));
+    }
+
+    pub fn scoring(&self) -> &Scoring {
+        &self.scoring
+    }
+
+    /// End the game once `goal` lines have been cleared, e.g. 40 for a sprint
+    pub fn set_line_goal(&mut self, goal: Option<u32>) {
+        self.line_goal = goal;
+    }
+
+    /// Set how many times a second `tick` is called. Gravity and lock delay
+    /// are defined in 60 Hz frames, which is the default
+    pub fn set_tick_rate(&mut self, hz: u32) {
+        self.frames_per_tick = 60.0 / hz.max(1) as f64;
+    }
+
+    /// Advance the game by one tick, applying gravity and lock delay. Does
+    /// nothing when gravity is off
+    pub fn tick(&mut self) {
+        if !self.playing() || !self.gravity.enabled() {
+            return;
+        }
+
+        self.fall += self.gravity.cells_per_frame() * self.frames_per_tick;
+        while self.fall >= 1.0 {
+            self.fall -= 1.0;
+            if !self.try_place_piece(Piece {
+                y: self.piece.y + 1,
+                ..self.piece
+            }) {
+                self.fall = 0.0;
+                break;
+            }
+        }
+
+        if self.on_ground() {
+            self.lock_frames += self.frames_per_tick;
+            if self.lock_frames >= self.gravity.lock_delay() as f64 || self.resets_exhausted {
+                self.lock_piece();
             }
+        } else {
+            self.lock_frames = 0.0;
         }
     }
 
-    pub fn get_hold_piece(&self) -> Option<PieceType> {
-        self.hold
+    fn on_ground(&mut self) -> bool {
+        !self.fits(Piece {
+            y: self.piece.y + 1,
+            ..self.piece
+        })
     }
 
-    pub fn next_piece(&mut self) {
-        let typ: PieceType;
-
-        match self.bag.pop() {
-            Some(p) => typ = p,
-            None => {
-                self.bag = self.next_bag;
-                self.next_bag = generate_bag();
-                typ = self.bag.pop().expect("next_bag is empty");
+    fn reset_lock(&mut self) {
+        if self.piece.y > self.lowest_y {
+            self.lowest_y = self.piece.y;
+            self.lock_resets = 0;
+            self.lock_frames = 0.0;
+            self.resets_exhausted = false;
+        } else if self.lock_frames > 0.0 {
+            if self.lock_resets < self.gravity.max_resets() {
+                self.lock_resets += 1;
+                self.lock_frames = 0.0;
+            } else {
+                self.resets_exhausted = true;
             }
         }
+    }
 
-        if self.try_place_piece(Piece {
-            x: 5,
-            y: 0,
+    pub fn set_kicks_180(&mut self, kicks: Kicks180) {
+        self.kicks_180 = kicks;
+    }
+
+    pub fn rotation(&self) -> &dyn RotationSystem {
+        self.rotation.as_ref()
+    }
+
+    /// Set before the game starts, the first piece is moved to the new
+    /// spawn position
+    pub fn set_rotation_system(&mut self, rotation: Box<dyn RotationSystem>) {
+        self.rotation = rotation;
+        if self.status == Status::Ready {
+            self.piece = self.spawn_position(self.piece.typ);
+            self.update_ghost();
+        }
+    }
+
+    pub fn dimensions(&self) -> Dimensions {
+        self.dims
+    }
+
+    /// Resize and clear the board, only possible before the game starts
+    pub fn set_dimensions(&mut self, dims: Dimensions) {
+        if self.status != Status::Ready {
+            return;
+        }
+
+        self.dims = dims;
+        self.rows = vec![Row::empty(dims.width); dims.height + dims.buffer];
+        self.piece = self.spawn_position(self.piece.typ);
+        self.update_ghost();
+    }
+
+    /// Where the rotation system spawns `typ`, kept centred on boards that
+    /// aren't the standard width
+    pub fn spawn_position(&self, typ: PieceType) -> Piece {
+        let (x, y, rot) = self.rotation.spawn(typ);
+        let offset = (self.dims.width as i32 - Dimensions::default().width as i32) / 2;
+        Piece {
+            x: x + offset,
+            y: (self.dims.buffer as i32 + y).max(0) as usize,
             typ,
-            rot: RotationState::None,
-        }) {
-            self.can_hold = true;
+            rot,
+        }
+    }
+
+    /// Number of pieces spawned so far, including ones swapped in by hold
+    pub fn spawns(&self) -> u64 {
+        self.spawns
+    }
+
+    pub fn piece(&self) -> Piece {
+        self.piece
+    }
+
+    pub fn get_next_piece(&mut self) -> PieceType {
+        self.preview(1)[0]
+    }
+
+    /// The next `n` pieces in order. Pieces are generated ahead of time as
+    /// needed, which doesn't change the sequence
+    pub fn preview(&mut self, n: usize) -> Vec<PieceType> {
+        while self.queue.len() < n {
+            let typ = self.randomizer.next(&mut self.rng);
+            self.queue.push_back(typ);
         }
+        self.queue.iter().take(n).copied().collect()
     }
 
+    pub fn get_hold_piece(&self) -> Option<PieceType> {
+        self.hold
+    }
+
+    /// Spawn the next piece from the queue
+    pub fn next_piece(&mut self) {
+        let typ = match self.queue.pop_front() {
+            Some(p) => p,
+            None => self.randomizer.next(&mut self.rng),
+        };
+        self.spawn_piece(typ)
+    }
+
+    /// Replace the active piece with `typ` at its spawn position. Whether
+    /// hold can be used is left as it was
     pub fn spawn_piece(&mut self, typ: PieceType) {
-        self.can_hold = true;
-        if self.try_place_piece(Piece {
-            x: 5,
-            y: 0,
-            typ,
-            rot: RotationState::None,
-        }) {
-            self.can_hold = true;
+        self.fall = 0.0;
+        self.soft_drop_cells = 0;
+        self.lock_frames = 0.0;
+        self.lock_resets = 0;
+        self.resets_exhausted = false;
+        self.lowest_y = 0;
+        if self.try_place_piece(self.spawn_position(typ)) {
+            self.spawns += 1;
+            self.events.push(GameEvent::Spawn(typ));
+        } else {
+            self.top_out(GameOverReason::BlockOut);
         }
     }
 
     pub fn drop(&mut self, hardly: bool) {
+        if !self.playing() {
+            return;
+        }
+
+        let mut cells = 0;
         while self.try_place_piece(Piece {
             y: self.piece.y + 1,
             ..self.piece
-        }) {}
+        }) {
+            cells += 1;
+        }
 
         if hardly {
-            self.lock_piece();
+            self.lock_piece_with_drop(cells);
+        } else {
+            self.soft_drop_cells += cells;
         }
     }
 
-    fn remove_line(&mut self, i: usize) {
-        for j in (1..i + 1).rev() {
-            self.rows[j] = self.rows[j - 1]
+    /// Remove full rows, returning where they were
+    fn check_clears(&mut self) -> Vec<usize> {
+        let full: Vec<usize> = (0..self.rows.len())
+            .filter(|y| self.rows[*y].cells.iter().all(|c| c.typ.is_some()))
+            .collect();
+        self.rows
+            .retain(|row| row.cells.iter().any(|c| c.typ.is_none()));
+
+        for _ in 0..full.len() {
+            self.rows.insert(0, Row::empty(self.dims.width));
         }
+
+        full
     }
 
-    fn check_clears(&mut self) {
-        let mut lines = Vec::new();
-        for (i, row) in self.rows.iter().enumerate() {
-            if row.cells[2..].iter().filter(|c| c.typ.is_none()).count() == 0 {
-                lines.push(i);
-            }
+    fn filled(&self, x: i32, y: i32) -> bool {
+        x < 0
+            || y < 0
+            || x >= self.dims.width as i32
+            || y >= self.rows.len() as i32
+            || self.rows[y as usize].cells[x as usize].typ.is_some()
+    }
+
+    /// T-spin check using the 3-corner rule, see
+    /// https://tetris.wiki/T-Spin. `kick` is the kick the last rotation of
+    /// `piece` used, 0 for 180 rotations, `None` if it moved since
+    pub fn spin_at(&self, piece: Piece, kick: Option<usize>) -> Spin {
+        let kick = match (piece.typ, kick) {
+            (PieceType::T, Some(k)) => k,
+            _ => return Spin::None,
+        };
+
+        // Corners are taken around the centre of the T, the front ones being
+        // on the side its stem points to
+        let coords = self
+            .rotation
+            .coords(piece.typ, piece.rot)
+            .iter()
+            .map(|(r, c)| (*r as i32, *c as i32))
+            .collect::<Vec<_>>();
+        let adjacent = |(r, c): (i32, i32)| coords.contains(&(r, c));
+        let (cr, cc) = match coords.iter().find(|(r, c)| {
+            [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                .iter()
+                .filter(|(dr, dc)| adjacent((r + dr, c + dc)))
+                .count()
+                == 3
+        }) {
+            Some(centre) => *centre,
+            None => return Spin::None,
+        };
+        let (sr, sc) = match [(-1, 0), (1, 0), (0, -1), (0, 1)]
+            .into_iter()
+            .find(|(dr, dc)| !adjacent((cr - dr, cc - dc)))
+        {
+            Some(stem) => stem,
+            None => return Spin::None,
+        };
+
+        let (x, y) = (piece.x + cc, piece.y as i32 + cr);
+        let corners = [(-1, -1), (-1, 1), (1, 1), (1, -1)];
+        let filled = corners.map(|(r, c)| self.filled(x + c, y + r));
+        if filled.iter().filter(|f| **f).count() < 3 {
+            return Spin::None;
         }
 
-        for i in lines {
-            self.remove_line(i);
+        let front: Vec<usize> = (0..4)
+            .filter(|i| {
+                let (r, c) = corners[*i];
+                (sr != 0 && r == sr) || (sc != 0 && c == sc)
+            })
+            .collect();
+        if front.iter().all(|i| filled[*i]) || kick == 4 {
+            Spin::Full
+        } else {
+            Spin::Mini
         }
     }
 
     fn lock_piece(&mut self) {
-        for (r, c) in get_coords(self.piece.typ, self.piece.rot) {
-            self.rows[self.piece.y + r].cells[self.piece.x + c].typ = Some(self.piece.typ)
+        self.lock_piece_with_drop(0)
+    }
+
+    fn lock_piece_with_drop(&mut self, hard_drop_cells: u32) {
+        let spin = self.spin_at(self.piece, self.last_kick);
+        let coords = self.rotation.coords(self.piece.typ, self.piece.rot);
+        for (r, c) in coords {
+            self.rows[self.piece.y + r].cells[(self.piece.x + *c as i32) as usize].typ =
+                Some(self.piece.typ)
         }
 
-        self.check_clears();
+        if coords
+            .iter()
+            .all(|(r, _)| self.piece.y + r < self.dims.buffer)
+        {
+            self.top_out(GameOverReason::LockOut);
+            return;
+        }
 
-        self.next_piece()
-    }
+        let rows = self.check_clears();
+        let perfect_clear = self
+            .rows
+            .iter()
+            .all(|row| row.cells.iter().all(|c| c.typ.is_none()));
+        let lock = LockEvent {
+            typ: self.piece.typ,
+            lines: rows.len() as u32,
+            spin,
+            perfect_clear,
+            soft_drop_cells: self.soft_drop_cells,
+            hard_drop_cells,
+        };
+        self.scoring.record(lock);
+        self.events.push(GameEvent::Lock {
+            piece: self.piece,
+            lock,
+        });
+        if !rows.is_empty() {
+            self.events.push(GameEvent::Clear { rows });
+        }
 
-    fn can_place_piece(&mut self, pc: Piece) -> bool {
-        for (r, c) in get_coords(pc.typ, pc.rot) {
-            if pc.x + c >= FIELD_WIDTH
-                || pc.y + r >= FIELD_HEIGHT
-                || pc.x + c < 2
-                || self.rows[pc.y + r].cells[pc.x + c].typ.is_some()
-            {
-                return false;
-            }
+        if self
+            .line_goal
+            .is_some_and(|goal| self.scoring.lines() >= goal)
+        {
+            self.status = Status::Finished;
+            return;
         }
 
-        true
+        self.can_hold = self.hold_mode != HoldMode::Disabled;
+        self.next_piece()
     }
 
     fn update_ghost(&mut self) {
         let mut piece = self.piece;
 
         loop {
-            if self.can_place_piece(piece) {
+            if self.fits(piece) {
                 self.ghost.x = piece.x;
                 self.ghost.y = piece.y;
                 piece = Piece {
@@ -288,69 +711,106 @@ impl State {
     }
 
     fn try_place_piece(&mut self, pc: Piece) -> bool {
-        if self.can_place_piece(pc) {
+        if self.fits(pc) {
             self.piece = pc;
+            self.last_kick = None;
             self.update_ghost();
+            self.reset_lock();
             true
         } else {
             false
         }
     }
 
-    pub fn get_cell_color(&self, x: usize, y: usize) -> Color {
+    /// Locked contents of a cell, `y` counted from the top of the hidden
+    /// buffer. `None` for empty and out of bounds cells
+    pub fn cell(&self, x: usize, y: usize) -> Option<PieceType> {
+        self.rows.get(y)?.cells.get(x)?.typ
+    }
+
+    /// Whether `piece` would fit on the board without overlapping the stack
+    pub fn fits(&self, piece: Piece) -> bool {
+        self.rotation
+            .coords(piece.typ, piece.rot)
+            .iter()
+            .all(|(r, c)| !self.filled(piece.x + *c as i32, (piece.y + r) as i32))
+    }
+
+    /// Get the contents of a visible cell, `(0, 0)` being the top left
+    pub fn get_cell(&self, x: usize, y: usize) -> CellView {
+        let y = y + self.dims.buffer;
+        let coords = self.rotation.coords(self.piece.typ, self.piece.rot);
+        let covers = |px: i32, py: usize| {
+            y >= py && x as i32 >= px && coords.contains(&(y - py, (x as i32 - px) as usize))
+        };
+
         match self.rows[y].cells[x].typ {
-            Some(t) => t.into(),
-            None => {
-                if x >= self.piece.x
-                    && y >= self.piece.y
-                    && get_coords(self.piece.typ, self.piece.rot)
-                        .contains(&(y - self.piece.y, x - self.piece.x))
-                {
-                    self.piece.typ.into()
-                } else if x >= self.ghost.x
-                    && y >= self.ghost.y
-                    && get_coords(self.piece.typ, self.piece.rot)
-                        .contains(&(y - self.ghost.y, x - self.ghost.x))
-                {
-                    Color::WHITE
-                } else {
-                    Color::BLACK
-                }
-            }
+            Some(t) => CellView::Filled(t),
+            None if covers(self.piece.x, self.piece.y) => CellView::Active(self.piece.typ),
+            None if covers(self.ghost.x, self.ghost.y) => CellView::Ghost,
+            None => CellView::Empty,
         }
     }
 
+    /// Snapshot of the visible board, indexed as `board()[y][x]`
+    pub fn board(&self) -> Vec<Vec<CellView>> {
+        (0..self.dims.height)
+            .map(|y| (0..self.dims.width).map(|x| self.get_cell(x, y)).collect())
+            .collect()
+    }
+
     pub fn _piece_up(&mut self) {
+        if !self.playing() {
+            return;
+        }
+
         self.try_place_piece(Piece {
             y: self.piece.y.saturating_sub(1),
             ..self.piece
         });
     }
 
-    pub fn _piece_down(&mut self) {
-        self.try_place_piece(Piece {
+    /// Soft drop by a single cell
+    pub fn piece_down(&mut self) {
+        if !self.playing() {
+            return;
+        }
+
+        if self.try_place_piece(Piece {
             y: self.piece.y + 1,
             ..self.piece
-        });
+        }) {
+            self.soft_drop_cells += 1;
+        }
     }
 
     pub fn piece_left(&mut self) {
+        if !self.playing() {
+            return;
+        }
+
         self.try_place_piece(Piece {
-            x: self.piece.x.saturating_sub(1),
+            x: self.piece.x - 1,
             ..self.piece
         });
     }
 
     pub fn snap_left(&mut self) {
-        while self.piece.x > 0
-            && self.try_place_piece(Piece {
-                x: self.piece.x.saturating_sub(1),
-                ..self.piece
-            })
-        {}
+        if !self.playing() {
+            return;
+        }
+
+        while self.try_place_piece(Piece {
+            x: self.piece.x - 1,
+            ..self.piece
+        }) {}
     }
 
     pub fn piece_right(&mut self) {
+        if !self.playing() {
+            return;
+        }
+
         self.try_place_piece(Piece {
             x: self.piece.x + 1,
             ..self.piece
@@ -358,77 +818,368 @@ impl State {
     }
 
     pub fn snap_right(&mut self) {
+        if !self.playing() {
+            return;
+        }
+
         while self.try_place_piece(Piece {
             x: self.piece.x + 1,
             ..self.piece
         }) {}
     }
 
-    pub fn rotate_right(&mut self) {
-        for (x, y) in get_wallkicks(self.piece.typ, self.piece.rot, self.piece.rot.right()) {
-            let newx = self.piece.x as i32 + x;
-            let newy = self.piece.y as i32 + y;
-
-            if (x, y) != (0, 0) {
-                println!("wallkick");
+    /// Where `piece` ends up rotated to `to`, along with the index of the kick
+    /// that let it fit. `None` if every kick is blocked
+    pub fn rotation_target(&self, piece: Piece, to: RotationState) -> Option<(Piece, usize)> {
+        let kicks = if to == piece.rot.flip() {
+            get_180_wallkicks(piece.typ, piece.rot, self.kicks_180)
+        } else {
+            let kicks = self.rotation.kicks(piece.typ, piece.rot, to);
+            // TGM's centre column rule, only rotate in place
+            match self.rotation.centre_column(piece.typ) {
+                Some(centre) if self.first_blocked(Piece { rot: to, ..piece }) == Some(centre) => {
+                    &kicks[..1]
+                }
+                _ => kicks,
             }
+        };
+        kicks.iter().enumerate().find_map(|(i, (x, y))| {
+            let target = Piece {
+                x: piece.x + x,
+                y: usize::try_from(piece.y as i32 + y).ok()?,
+                rot: to,
+                ..piece
+            };
+            self.fits(target).then_some((target, i))
+        })
+    }
 
-            if newx >= 0
-                && newy >= 0
-                && self.try_place_piece(Piece {
-                    x: newx as usize,
-                    y: newy as usize,
-                    typ: self.piece.typ,
-                    rot: self.piece.rot.right(),
-                })
-            {
-                return;
-            }
-        }
+    /// Column of the shape where `piece` first overlaps the stack or a
+    /// wall, reading its cells left to right and top to bottom
+    fn first_blocked(&self, piece: Piece) -> Option<usize> {
+        let mut coords = self.rotation.coords(piece.typ, piece.rot).to_vec();
+        coords.sort();
+        coords
+            .into_iter()
+            .find(|(r, c)| self.filled(piece.x + *c as i32, piece.y as i32 + *r as i32))
+            .map(|(_, c)| c)
+    }
+
+    pub fn rotate_right(&mut self) {
+        self.rotate(self.piece.rot.right())
     }
 
     pub fn rotate_left(&mut self) {
-        for (x, y) in get_wallkicks(self.piece.typ, self.piece.rot, self.piece.rot.left()) {
-            let newx = self.piece.x as i32 + x;
-            let newy = self.piece.y as i32 + y;
-
-            if (x, y) != (0, 0) {
-                println!(
-                    "wallkick {} | {} | {} | {}",
-                    self.piece.x, self.piece.y, newx, newy
-                );
-            }
+        self.rotate(self.piece.rot.left())
+    }
 
-            if newx >= 0
-                && newy >= 0
-                && self.try_place_piece(Piece {
-                    x: newx as usize,
-                    y: newy as usize,
-                    typ: self.piece.typ,
-                    rot: self.piece.rot.left(),
-                })
-            {
-                return;
-            }
+    pub fn rotate_180(&mut self) {
+        self.rotate(self.piece.rot.flip())
+    }
+
+    fn rotate(&mut self, to: RotationState) {
+        if !self.playing() {
+            return;
         }
+
+        let from = self.piece.rot;
+        if let Some((piece, kick)) = self.rotation_target(self.piece, to) {
+            self.try_place_piece(piece);
+            self.last_kick = Some(Self::spin_kick(from, to, kick));
+            self.rotated(from, kick);
+        }
+    }
+
+    /// Kick index spin detection is given for a rotation. Only the last SRS
+    /// kick upgrades a T-spin mini, so it doesn't get the index into a 180
+    /// table
+    pub(crate) fn spin_kick(from: RotationState, to: RotationState, kick: usize) -> usize {
+        if to == from.flip() {
+            0
+        } else {
+            kick
+        }
+    }
+
+    fn rotated(&mut self, from: RotationState, kick: usize) {
+        self.events.push(GameEvent::Rotate {
+            from,
+            to: self.piece.rot,
+            kick,
+        });
+    }
+
+    pub fn hold_mode(&self) -> HoldMode {
+        self.hold_mode
+    }
+
+    pub fn set_hold_mode(&mut self, mode: HoldMode) {
+        self.hold_mode = mode;
+        self.can_hold = mode != HoldMode::Disabled;
     }
 
+    /// Whether `hold` would do anything for the active piece
     pub fn can_hold(&self) -> bool {
         self.can_hold
     }
 
     pub fn hold(&mut self) {
-        match &self.hold {
-            Some(p) => {
-                let new_pc = *p;
-                self.hold = Some(self.piece.typ);
-                self.spawn_piece(new_pc);
-                self.can_hold = false;
-            }
-            None => {
-                self.hold = Some(self.piece.typ);
-                self.next_piece();
+        if !self.playing() || !self.can_hold {
+            return;
+        }
+
+        let held = self.piece.typ;
+        let released = match self.hold_mode {
+            HoldMode::Disabled => return,
+            HoldMode::SwapNext => {
+                let next = self.get_next_piece();
+                self.queue[0] = held;
+                Some(next)
             }
+            HoldMode::Guideline | HoldMode::Infinite => self.hold.replace(held),
+        };
+        self.events.push(GameEvent::Hold { held, released });
+
+        self.can_hold = self.hold_mode == HoldMode::Infinite;
+        match released {
+            Some(typ) => self.spawn_piece(typ),
+            None => self.next_piece(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{gravity::GravityMode, rotations::RotationSystemKind};
+
+    /// A game at 20G, so the first tick puts the piece on the ground
+    fn grounded(lock_delay: u32, max_resets: u32) -> State {
+        let mut state = State::with_seed(0);
+        state.set_gravity(Gravity::new(
+            GravityMode::Constant(20.0),
+            lock_delay,
+            max_resets,
+        ));
+        state.start();
+        state
+    }
+
+    /// Whether a piece has locked onto the board
+    fn locked(state: &State) -> bool {
+        state
+            .board()
+            .iter()
+            .flatten()
+            .any(|c| matches!(c, CellView::Filled(_)))
+    }
+
+    #[test]
+    fn locks_after_lock_delay() {
+        let mut state = grounded(30, 15);
+        for _ in 0..29 {
+            state.tick();
+        }
+        assert!(!locked(&state));
+        state.tick();
+        assert!(locked(&state));
+    }
+
+    #[test]
+    fn no_resets_still_waits_for_lock_delay() {
+        let mut state = grounded(30, 0);
+        state.tick();
+        assert!(!locked(&state));
+        for _ in 0..29 {
+            state.tick();
+        }
+        assert!(locked(&state));
+    }
+
+    #[test]
+    fn moving_on_the_ground_resets_lock_delay() {
+        let mut state = grounded(30, 15);
+        for _ in 0..20 {
+            state.tick();
+        }
+        state.piece_left();
+        for _ in 0..29 {
+            state.tick();
+        }
+        assert!(!locked(&state));
+        state.tick();
+        assert!(locked(&state));
+    }
+
+    #[test]
+    fn locks_once_resets_run_out() {
+        let mut state = grounded(30, 2);
+        state.tick();
+        state.piece_left();
+        state.tick();
+        state.piece_right();
+        state.tick();
+        assert!(!locked(&state));
+        // Refused a reset, so the next tick on the ground locks
+        state.piece_left();
+        state.tick();
+        assert!(locked(&state));
+    }
+
+    #[test]
+    fn no_resets_locks_on_the_first_move() {
+        let mut state = grounded(30, 0);
+        state.tick();
+        state.piece_left();
+        assert!(!locked(&state));
+        state.tick();
+        assert!(locked(&state));
+    }
+
+    /// A board with `cells` filled, as (column, row) with row 0 the bottom
+    fn board(cells: &[(usize, usize)]) -> State {
+        let mut state = State::with_seed(0);
+        let bottom = state.rows.len() - 1;
+        for (x, y) in cells {
+            state.rows[bottom - y].cells[*x].typ = Some(PieceType::O);
+        }
+        state
+    }
+
+    /// A T pointing down with its centre at column 4, row 1
+    fn t_down(state: &State) -> Piece {
+        Piece::new(PieceType::T, RotationState::Flip, 3, state.rows.len() - 3)
+    }
+
+    #[test]
+    fn spin_with_both_front_corners_is_full() {
+        let state = board(&[(3, 0), (5, 0), (3, 2)]);
+        assert_eq!(state.spin_at(t_down(&state), Some(0)), Spin::Full);
+    }
+
+    #[test]
+    fn spin_with_one_front_corner_is_mini() {
+        let state = board(&[(3, 0), (3, 2), (5, 2)]);
+        assert_eq!(state.spin_at(t_down(&state), Some(0)), Spin::Mini);
+        // The last kick of the table upgrades a mini, as in a TST kick
+        assert_eq!(state.spin_at(t_down(&state), Some(4)), Spin::Full);
+    }
+
+    #[test]
+    fn no_spin() {
+        let state = board(&[(3, 0), (5, 0), (3, 2)]);
+        // Moved since the last rotation
+        assert_eq!(state.spin_at(t_down(&state), None), Spin::None);
+        let o = Piece::new(PieceType::O, RotationState::None, 3, state.rows.len() - 3);
+        assert_eq!(state.spin_at(o, Some(0)), Spin::None);
+
+        let state = board(&[(3, 0), (5, 0)]);
+        assert_eq!(state.spin_at(t_down(&state), Some(0)), Spin::None);
+    }
+
+    fn sequence(seed: u64, n: usize) -> Vec<PieceType> {
+        let mut state = State::with_seed(seed);
+        state.start();
+        (0..n)
+            .map(|_| {
+                let typ = state.piece().typ();
+                state.next_piece();
+                typ
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seed_decides_the_pieces() {
+        assert_eq!(sequence(1, 50), sequence(1, 50));
+        assert_ne!(sequence(1, 50), sequence(2, 50));
+    }
+
+    #[test]
+    fn centre_column_stops_kicks() {
+        let mut state = board(&[(4, 2)]);
+        state.set_rotation_system(RotationSystemKind::Ars.build());
+        let t = Piece::new(PieceType::T, RotationState::None, 3, state.rows.len() - 3);
+        assert_eq!(state.rotation_target(t, RotationState::Right), None);
+
+        // Blocked at the side first, so it kicks right
+        let mut state = board(&[(3, 1)]);
+        state.set_rotation_system(RotationSystemKind::Ars.build());
+        let t = Piece::new(PieceType::T, RotationState::Flip, 3, state.rows.len() - 3);
+        let (kicked, _) = state.rotation_target(t, RotationState::Right).unwrap();
+        assert_eq!(kicked.x(), 4);
+    }
+
+    fn holding(mode: HoldMode) -> State {
+        let mut state = State::with_seed(0);
+        state.set_hold_mode(mode);
+        state.start();
+        state
+    }
+
+    #[test]
+    fn preview_shows_what_spawns() {
+        let mut state = holding(HoldMode::Guideline);
+        let next = state.preview(5);
+        // The first hold takes the next piece from the queue
+        state.hold();
+        assert_eq!(state.piece().typ(), next[0]);
+        assert_eq!(state.preview(4), next[1..]);
+        for typ in &next[1..] {
+            state.next_piece();
+            assert_eq!(state.piece().typ(), *typ);
         }
     }
+
+    #[test]
+    fn disabled_hold_does_nothing() {
+        let mut state = holding(HoldMode::Disabled);
+        let piece = state.piece();
+        state.hold();
+        assert_eq!(state.piece(), piece);
+        assert_eq!(state.get_hold_piece(), None);
+    }
+
+    #[test]
+    fn guideline_hold_once_per_piece() {
+        let mut state = holding(HoldMode::Guideline);
+        let first = state.piece().typ();
+        state.hold();
+        assert_eq!(state.get_hold_piece(), Some(first));
+        assert!(!state.can_hold());
+
+        let second = state.piece();
+        state.hold();
+        assert_eq!(state.piece(), second);
+        assert_eq!(state.get_hold_piece(), Some(first));
+
+        state.drop(true);
+        assert!(state.can_hold());
+        state.hold();
+        assert_eq!(state.piece().typ(), first);
+    }
+
+    #[test]
+    fn infinite_hold_swaps_back_and_forth() {
+        let mut state = holding(HoldMode::Infinite);
+        let first = state.piece().typ();
+        state.hold();
+        let second = state.piece().typ();
+        state.hold();
+        assert!(state.can_hold());
+        assert_eq!(state.piece().typ(), first);
+        assert_eq!(state.get_hold_piece(), Some(second));
+    }
+
+    #[test]
+    fn swap_next_swaps_with_the_queue() {
+        let mut state = holding(HoldMode::SwapNext);
+        let first = state.piece().typ();
+        let next = state.get_next_piece();
+        state.hold();
+        assert_eq!(state.piece().typ(), next);
+        assert_eq!(state.get_next_piece(), first);
+        assert_eq!(state.get_hold_piece(), None);
+        assert!(!state.can_hold());
+    }
 }
@@ -0,0 +1,130 @@
+// Live statistics for a game, worked out from the keys pressed and the
+// events the game emits rather than by looking at `State`
+
+use std::time::Duration;
+
+use crate::{event::GameEvent, input::Key, scoring::Scoring};
+
+#[derive(Clone, Debug, Default)]
+pub struct Stats {
+    keys: u32,
+    pieces: u32,
+    /// Kept from lock events for lines and attack
+    scoring: Scoring,
+    elapsed: Duration,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a key press, only presses of game keys are given
+    pub fn input(&mut self, _key: Key, press: bool) {
+        if press {
+            self.keys += 1;
+        }
+    }
+
+    pub fn event(&mut self, event: &GameEvent) {
+        if let GameEvent::Lock { lock, .. } = event {
+            self.pieces += 1;
+            self.scoring.record(*lock);
+        }
+    }
+
+    /// Add `dt` of play to the clock, the caller leaves out pauses
+    pub fn advance(&mut self, dt: Duration) {
+        self.elapsed += dt;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Pieces locked
+    pub fn pieces(&self) -> u32 {
+        self.pieces
+    }
+
+    pub fn lines(&self) -> u32 {
+        self.scoring.lines()
+    }
+
+    pub fn attack(&self) -> u32 {
+        self.scoring.attack()
+    }
+
+    /// Pieces per second
+    pub fn pps(&self) -> f64 {
+        per(self.pieces, self.elapsed.as_secs_f64())
+    }
+
+    /// Keys pressed per piece locked
+    pub fn kpp(&self) -> f64 {
+        per(self.keys, self.pieces as f64)
+    }
+
+    /// Attack per minute
+    pub fn apm(&self) -> f64 {
+        per(self.attack(), self.elapsed.as_secs_f64() / 60.0)
+    }
+}
+
+/// `n` divided by `d`, 0 before there's anything to divide by
+fn per(n: u32, d: f64) -> f64 {
+    if d > 0.0 {
+        n as f64 / d
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        scoring::{LockEvent, Spin},
+        state::{Piece, PieceType, RotationState},
+    };
+
+    fn lock(lines: u32) -> GameEvent {
+        GameEvent::Lock {
+            piece: Piece::new(PieceType::I, RotationState::None, 0, 0),
+            lock: LockEvent {
+                typ: PieceType::I,
+                lines,
+                spin: Spin::None,
+                perfect_clear: false,
+                soft_drop_cells: 0,
+                hard_drop_cells: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn nothing_to_divide_by() {
+        let stats = Stats::new();
+        assert_eq!((stats.pps(), stats.kpp(), stats.apm()), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rates() {
+        let mut stats = Stats::new();
+        for _ in 0..6 {
+            stats.input(Key::Left, true);
+            stats.input(Key::Left, false);
+        }
+        for lines in [0, 0, 4] {
+            stats.event(&lock(lines));
+        }
+        stats.advance(Duration::from_secs(30));
+
+        assert_eq!(stats.pieces(), 3);
+        assert_eq!(stats.lines(), 4);
+        assert_eq!(stats.attack(), 4);
+        assert_eq!(stats.pps(), 0.1);
+        assert_eq!(stats.kpp(), 2.0);
+        assert_eq!(stats.apm(), 8.0);
+    }
+}
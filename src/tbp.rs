@@ -0,0 +1,448 @@
+// Tetris Bot Protocol, https://github.com/tetris-bot-protocol/tbp-spec. A bot
+// is a child process exchanging one JSON message per line over its stdin and
+// stdout. Positions in messages have y pointing up from the bottom row of a
+// 40 row board, and name the centre of the piece as SRS rotates it
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    iter,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    movegen::{cells, placements, Placement, Step},
+    scoring::Spin,
+    state::{Piece, PieceType, RotationState, State, Status},
+    TetrErr,
+};
+
+/// Rows in a TBP board, the board is padded with empty rows up to this
+const BOARD_ROWS: usize = 40;
+
+/// How long a bot gets to answer while starting up
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A final position for the active piece suggested by a bot
+#[derive(Clone, Debug)]
+pub struct Move {
+    pub piece: Piece,
+    /// The spin the bot expects the piece to lock with
+    pub spin: Spin,
+    /// The move as the bot sent it, echoed back when it's played
+    json: Value,
+}
+
+fn orientation(name: &str) -> Option<RotationState> {
+    match name {
+        "north" => Some(RotationState::None),
+        "east" => Some(RotationState::Right),
+        "south" => Some(RotationState::Flip),
+        "west" => Some(RotationState::Left),
+        _ => None,
+    }
+}
+
+/// Cells of `typ` facing north relative to its centre, y up
+fn north_cells(typ: PieceType) -> [(i64, i64); 4] {
+    match typ {
+        PieceType::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        PieceType::J => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+        PieceType::L => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+        PieceType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        PieceType::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+        PieceType::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        PieceType::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+    }
+}
+
+/// Where the active piece has to be to cover the cells of a TBP location.
+/// `None` if it's off the board or the rotation system's shape for that
+/// orientation doesn't match
+fn parse_location(loc: &Value, state: &State) -> Option<Piece> {
+    let typ = PieceType::from_name(loc.get("type")?.as_str()?)?;
+    let rot = orientation(loc.get("orientation")?.as_str()?)?;
+    let (x, y) = (loc.get("x")?.as_i64()?, loc.get("y")?.as_i64()?);
+    let dims = state.dimensions();
+    let rows = (dims.height + dims.buffer) as i64;
+
+    // As (row, column), rows counted from the top like the rest of the game
+    let mut cells: Vec<(i64, i64)> = north_cells(typ)
+        .iter()
+        .map(|&(cx, cy)| {
+            let (cx, cy) = match rot {
+                RotationState::None => (cx, cy),
+                RotationState::Right => (cy, -cx),
+                RotationState::Flip => (-cx, -cy),
+                RotationState::Left => (-cy, cx),
+            };
+            (rows - 1 - (y + cy), x + cx)
+        })
+        .collect();
+    cells.sort();
+
+    let coords = state.rotation().coords(typ, rot);
+    let top = coords.iter().map(|(r, _)| *r as i64).min()?;
+    let left = coords.iter().map(|(_, c)| *c as i64).min()?;
+    let py = cells.iter().map(|(r, _)| *r).min()? - top;
+    let px = cells.iter().map(|(_, c)| *c).min()? - left;
+    if py < 0 {
+        return None;
+    }
+
+    let mut ours: Vec<(i64, i64)> = coords
+        .iter()
+        .map(|(r, c)| (py + *r as i64, px + *c as i64))
+        .collect();
+    ours.sort();
+    (ours == cells).then(|| Piece::new(typ, rot, px as i32, py as usize))
+}
+
+fn parse_move(mv: &Value, state: &State) -> Option<Move> {
+    Some(Move {
+        piece: parse_location(mv.get("location")?, state)?,
+        spin: match mv.get("spin").and_then(Value::as_str) {
+            Some("mini") => Spin::Mini,
+            Some("full") => Spin::Full,
+            _ => Spin::None,
+        },
+        json: mv.clone(),
+    })
+}
+
+/// The first of `moves` that can be played right now, and how. The game is
+/// the referee, so the piece (or the one hold would bring out) has to be
+/// able to get there with real inputs and lock with the spin the bot claims
+pub fn first_playable(state: &mut State, moves: &[Move]) -> Option<(Move, Placement)> {
+    let reachable: Vec<_> = placements(state)
+        .into_iter()
+        .map(|p| (cells(state, p.piece), p))
+        .collect();
+    moves.iter().find_map(|mv| {
+        let target = cells(state, mv.piece);
+        reachable
+            .iter()
+            .find(|(covers, p)| {
+                p.piece.typ() == mv.piece.typ() && p.spin == mv.spin && *covers == target
+            })
+            .map(|(_, p)| (mv.clone(), p.clone()))
+    })
+}
+
+/// The whole game as a `start` message, `queue` starting with the active
+/// piece
+fn start_message(state: &State, queue: &[PieceType]) -> Value {
+    let dims = state.dimensions();
+    let rows = dims.height + dims.buffer;
+    let board: Vec<Vec<Option<&str>>> = (0..BOARD_ROWS)
+        .map(|y| {
+            (0..dims.width)
+                .map(|x| {
+                    rows.checked_sub(y + 1)
+                        .and_then(|row| state.cell(x, row))
+                        .map(|t| t.name())
+                })
+                .collect()
+        })
+        .collect();
+
+    json!({
+        "type": "start",
+        "hold": state.get_hold_piece().map(|t| t.name()),
+        "queue": queue.iter().map(|t| t.name()).collect::<Vec<_>>(),
+        "combo": state.scoring().combo().map_or(0, |c| c + 1),
+        "back_to_back": state.scoring().back_to_back(),
+        "board": board,
+    })
+}
+
+/// A running bot
+pub struct Bot {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    name: String,
+    previews: usize,
+    /// The queue as the bot knows it, starting with the active piece
+    queue: Vec<PieceType>,
+    started: bool,
+    /// Suggestions asked for but not received, only the last one is used
+    pending: u32,
+}
+
+impl Bot {
+    /// Start `command` (a program and its arguments) and agree on the rules,
+    /// telling it about `previews` pieces after the active one
+    pub fn spawn(command: &[String], previews: usize) -> Result<Self, TetrErr> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| TetrErr::Str("Bot command is empty".to_string()))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Unable to start bot {}: {}", program, e))?;
+        let stdin = child.stdin.take().expect("Bot stdin wasn't piped");
+        let stdout = child.stdout.take().expect("Bot stdout wasn't piped");
+
+        // Reading blocks, so lines are passed over from a thread
+        let (tx, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut bot = Bot {
+            child,
+            stdin,
+            lines,
+            name: program.clone(),
+            previews,
+            queue: Vec::new(),
+            started: false,
+            pending: 0,
+        };
+
+        let info = bot.wait_for("info")?;
+        if let (Some(name), Some(version)) = (
+            info.get("name").and_then(Value::as_str),
+            info.get("version").and_then(Value::as_str),
+        ) {
+            bot.name = format!("{} {}", name, version);
+        }
+        bot.send(json!({ "type": "rules" }))?;
+        bot.wait_for("ready")?;
+        Ok(bot)
+    }
+
+    /// Name and version the bot gave
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn send(&mut self, msg: Value) -> Result<(), TetrErr> {
+        writeln!(self.stdin, "{}", msg)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Next message, waiting up to `timeout` for one if given
+    fn receive(&mut self, timeout: Option<Duration>) -> Result<Option<Value>, TetrErr> {
+        let exited = || TetrErr::Str(format!("Bot {} exited", self.name));
+        let line = match timeout {
+            Some(timeout) => match self.lines.recv_timeout(timeout) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(RecvTimeoutError::Disconnected) => return Err(exited()),
+            },
+            None => match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(exited()),
+            },
+        };
+        serde_json::from_str(&line)
+            .map(Some)
+            .map_err(|e| TetrErr::Str(format!("Bot {} sent invalid JSON: {}", self.name, e)))
+    }
+
+    /// Skip messages until one of type `typ`, failing on an error message
+    fn wait_for(&mut self, typ: &str) -> Result<Value, TetrErr> {
+        loop {
+            let msg = self.receive(Some(STARTUP_TIMEOUT))?.ok_or_else(|| {
+                TetrErr::Str(format!(
+                    "Timed out waiting for {} from bot {}",
+                    typ, self.name
+                ))
+            })?;
+            match msg.get("type").and_then(Value::as_str) {
+                Some(t) if t == typ => return Ok(msg),
+                Some("error") => {
+                    return Err(TetrErr::Str(format!(
+                        "Bot {} refused to play: {}",
+                        self.name,
+                        msg.get("reason").unwrap_or(&Value::Null)
+                    )))
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Tell the bot the whole state of the game, replacing whatever it knew,
+    /// and ask it for a move
+    pub fn start(&mut self, state: &mut State) -> Result<(), TetrErr> {
+        if state.dimensions().width != 10 {
+            return Err(TetrErr::Str(
+                "Bots can only play on a 10 wide board".to_string(),
+            ));
+        }
+        if self.started {
+            self.send(json!({ "type": "stop" }))?;
+        }
+        self.queue = iter::once(state.piece().typ())
+            .chain(state.preview(self.previews))
+            .collect();
+        self.send(start_message(state, &self.queue))?;
+        self.started = true;
+        self.suggest()
+    }
+
+    fn suggest(&mut self) -> Result<(), TetrErr> {
+        self.pending += 1;
+        self.send(json!({ "type": "suggest" }))
+    }
+
+    /// The moves the bot suggested, best first, once it has answered. Moves
+    /// that don't fit on the board are left out
+    pub fn poll(&mut self, state: &State) -> Result<Option<Vec<Move>>, TetrErr> {
+        while let Some(msg) = self.receive(None)? {
+            if msg.get("type").and_then(Value::as_str) != Some("suggestion") {
+                continue;
+            }
+            self.pending = self.pending.saturating_sub(1);
+            if self.pending > 0 {
+                continue;
+            }
+            let moves = match msg.get("moves").and_then(Value::as_array) {
+                Some(moves) => moves.iter().filter_map(|m| parse_move(m, state)).collect(),
+                None => Vec::new(),
+            };
+            return Ok(Some(moves));
+        }
+        Ok(None)
+    }
+
+    /// Play the first of `moves` that's possible, tell the bot about it and
+    /// ask for the next one. Returns `false` if none of them were
+    pub fn play(&mut self, state: &mut State, moves: &[Move]) -> Result<bool, TetrErr> {
+        let (mv, placement) = match first_playable(state, moves) {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+
+        let hold_empty = state.get_hold_piece().is_none();
+        let held = placement.steps.first() == Some(&Step::Hold);
+        placement.play(state);
+        self.send(json!({ "type": "play", "move": mv.json }))?;
+        if state.status() != Status::Playing {
+            return Ok(true);
+        }
+
+        // The bot takes what it used off the front of its queue, then gets
+        // told about pieces that have come into view since
+        let used = if held && hold_empty { 2 } else { 1 };
+        let known = self.queue.split_off(used.min(self.queue.len()));
+        let queue: Vec<PieceType> = iter::once(state.piece().typ())
+            .chain(state.preview(self.previews))
+            .collect();
+        if !queue.starts_with(&known) {
+            // Hold rules TBP doesn't know about can get the bot out of step
+            return self.start(state).map(|_| true);
+        }
+        for typ in &queue[known.len()..] {
+            self.send(json!({ "type": "new_piece", "piece": typ.name() }))?;
+        }
+        self.queue = queue;
+        self.suggest().map(|_| true)
+    }
+}
+
+impl Drop for Bot {
+    fn drop(&mut self) {
+        let _ = self.send(json!({ "type": "quit" }));
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::HoldMode;
+
+    fn location(typ: &str, orientation: &str, x: i64, y: i64) -> Value {
+        json!({ "type": typ, "orientation": orientation, "x": x, "y": y })
+    }
+
+    /// Cells as (x, row from the bottom)
+    fn board_cells(state: &State, piece: Piece) -> Vec<(i32, i64)> {
+        let rows = (state.dimensions().height + state.dimensions().buffer) as i64;
+        let mut cells: Vec<_> = cells(state, piece)
+            .into_iter()
+            .map(|(x, y)| (x, rows - 1 - y as i64))
+            .collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn locations_name_the_centre() {
+        let state = State::with_seed(0);
+        let t = parse_location(&location("T", "north", 4, 0), &state).unwrap();
+        assert_eq!(board_cells(&state, t), [(3, 0), (4, 0), (4, 1), (5, 0)]);
+        let i = parse_location(&location("I", "east", 0, 2), &state).unwrap();
+        assert_eq!(board_cells(&state, i), [(0, 0), (0, 1), (0, 2), (0, 3)]);
+    }
+
+    #[test]
+    fn every_piece_and_orientation() {
+        let state = State::with_seed(0);
+        for typ in PieceType::ALL {
+            for (name, turn) in [("north", 0), ("east", 1), ("south", 2), ("west", 3)] {
+                let piece = parse_location(&location(typ.name(), name, 4, 5), &state)
+                    .unwrap_or_else(|| panic!("{:?} {}", typ, name));
+                assert_eq!(piece.rot(), orientation(name).unwrap());
+
+                // Turning the north cells clockwise about the centre
+                let mut expected: Vec<(i32, i64)> = north_cells(typ)
+                    .iter()
+                    .map(|&(x, y)| (0..turn).fold((x, y), |(x, y), _| (y, -x)))
+                    .map(|(x, y)| (4 + x as i32, 5 + y))
+                    .collect();
+                expected.sort();
+                assert_eq!(board_cells(&state, piece), expected, "{:?} {}", typ, name);
+            }
+        }
+    }
+
+    fn playing_t() -> State {
+        let mut state = State::with_seed(0);
+        state.set_hold_mode(HoldMode::Disabled);
+        state.start();
+        state.spawn_piece(PieceType::T);
+        state
+    }
+
+    fn t_move(y: i64, spin: &str) -> Move {
+        let state = playing_t();
+        parse_move(
+            &json!({ "location": location("T", "north", 4, y), "spin": spin }),
+            &state,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn referees_moves() {
+        let mut state = playing_t();
+        // Left in the air, and claiming a spin it can't get
+        let unreachable = [t_move(10, "none"), t_move(0, "full")];
+        assert!(first_playable(&mut state, &unreachable).is_none());
+
+        let moves = [t_move(10, "none"), t_move(0, "none")];
+        let (played, placement) = first_playable(&mut state, &moves).unwrap();
+        assert_eq!(played.piece, moves[1].piece);
+        placement.play(&mut state);
+        assert_eq!(state.cell(4, 21), Some(PieceType::T));
+        assert_eq!(state.cell(4, 20), Some(PieceType::T));
+    }
+}
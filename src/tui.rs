@@ -0,0 +1,358 @@
+// Terminal frontend, drawing each cell as two characters with a coloured
+// background. Key releases are only reported by terminals supporting the
+// kitty keyboard protocol, elsewhere every key press is a tap
+
+use std::{
+    io::{stdout, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::{Color, Print, ResetColor, SetBackgroundColor},
+    terminal::{
+        self, supports_keyboard_enhancement, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+
+use crate::{
+    config::{terminal_key_name, terminal_keys, KeyConfig},
+    event::GameEvent,
+    game::{self, load_config, Frontend, Input},
+    input::Action,
+    render::{piece_cells, ClearFlash, Renderer},
+    replay::Replay,
+    rotations::RotationSystem,
+    state::{CellView, Piece, PieceType, RotationState, State, Status},
+    TetrErr,
+};
+
+type Rgb = (u8, u8, u8);
+
+const BORDER: Rgb = (128, 128, 128);
+const BOARD: Rgb = (0, 0, 0);
+
+/// Puts the terminal in raw mode on the alternate screen, and restores it
+/// when dropped so that errors and panics don't leave it unusable
+struct TerminalGuard {
+    enhanced: bool,
+}
+
+impl TerminalGuard {
+    fn new() -> Result<Self, TetrErr> {
+        terminal::enable_raw_mode()?;
+        let guard = TerminalGuard {
+            enhanced: supports_keyboard_enhancement().unwrap_or(false),
+        };
+        execute!(stdout(), EnterAlternateScreen, Hide)?;
+        if guard.enhanced {
+            execute!(
+                stdout(),
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+            )?;
+        }
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if self.enhanced {
+            let _ = execute!(stdout(), PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout(), ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Cells of one frame, `None` being the terminal's own background
+struct Grid {
+    width: usize,
+    cells: Vec<Vec<Option<Rgb>>>,
+}
+
+impl Grid {
+    fn new(width: usize, height: usize) -> Self {
+        Grid {
+            width,
+            cells: vec![vec![None; width]; height],
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, colour: Rgb) {
+        if y >= self.cells.len() {
+            self.cells.resize(y + 1, vec![None; self.width]);
+        }
+        if x < self.width {
+            self.cells[y][x] = Some(colour);
+        }
+    }
+
+    /// Draw `typ` in its spawn orientation, the top left of its shape at
+    /// `(x, y)`. Returns the number of rows it takes up
+    fn piece(
+        &mut self,
+        rotation: &dyn RotationSystem,
+        typ: PieceType,
+        colour: Rgb,
+        (x, y): (usize, usize),
+    ) -> usize {
+        let coords = rotation.coords(typ, RotationState::None);
+        for (r, c) in coords {
+            self.set(x + c, y + r, colour);
+        }
+        coords.iter().map(|(r, _)| r + 1).max().unwrap_or(0)
+    }
+}
+
+/// Widest piece in its spawn orientation, in cells
+fn piece_width(rotation: &dyn RotationSystem) -> usize {
+    PieceType::ALL
+        .iter()
+        .flat_map(|typ| rotation.coords(*typ, RotationState::None))
+        .map(|(_, c)| c + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+fn cell_colour(state: &State, cell: CellView) -> Option<Rgb> {
+    match (state.status(), cell) {
+        (_, CellView::Empty) => None,
+        (_, CellView::Ghost) => Some((255, 255, 255)),
+        (Status::Over(_) | Status::Finished, CellView::Filled(_)) => Some(BORDER),
+        (_, CellView::Active(t) | CellView::Filled(t)) => Some(state.rotation().colour(t)),
+    }
+}
+
+/// Lay out the hold piece left of the board and the previews right of it
+fn build_grid(state: &mut State, previews: usize, flash: &[usize], hint: Option<Piece>) -> Grid {
+    let dims = state.dimensions();
+    let next = state.preview(previews);
+    let rotation = state.rotation();
+    let side = piece_width(rotation) + 2;
+    let board_x = side + 1;
+    let mut grid = Grid::new(side + dims.width + 2 + side, dims.height + 1);
+
+    for y in 0..=dims.height {
+        grid.set(side, y, BORDER);
+        grid.set(board_x + dims.width, y, BORDER);
+    }
+    for x in 0..dims.width {
+        grid.set(board_x + x, dims.height, BORDER);
+    }
+
+    if let Some(hold) = state.get_hold_piece() {
+        let colour = if state.can_hold() {
+            rotation.colour(hold)
+        } else {
+            BORDER
+        };
+        grid.piece(rotation, hold, colour, (1, 1));
+    }
+    let mut y = 1;
+    for typ in next {
+        y += grid.piece(
+            rotation,
+            typ,
+            rotation.colour(typ),
+            (board_x + dims.width + 2, y),
+        ) + 1;
+    }
+
+    let paused = state.status() == Status::Paused;
+    // Hints are drawn dimmed wherever nothing else is
+    let (hint_cells, hint_colour) = match hint {
+        Some(p) => {
+            let (r, g, b) = state.rotation().colour(p.typ());
+            (piece_cells(state, p), (r / 2, g / 2, b / 2))
+        }
+        None => (vec![], BOARD),
+    };
+    for (y, row) in state.board().into_iter().enumerate() {
+        for (x, cell) in row.into_iter().enumerate() {
+            let hinted =
+                matches!(cell, CellView::Empty | CellView::Ghost) && hint_cells.contains(&(x, y));
+            let colour = match paused {
+                true => None,
+                false if flash.contains(&y) => Some((255, 255, 255)),
+                false if hinted => Some(hint_colour),
+                false => cell_colour(state, cell),
+            };
+            grid.set(board_x + x, y, colour.unwrap_or(BOARD));
+        }
+    }
+
+    grid
+}
+
+struct TuiFrontend {
+    keys: KeyConfig<KeyCode>,
+    previews: usize,
+    /// Whether the terminal reports key releases
+    releases: bool,
+    /// Output of the last frame, redrawn only when it changes
+    last: Vec<u8>,
+    messages: Vec<String>,
+    flash: ClearFlash,
+    hint: Option<Piece>,
+    stats: Vec<(&'static str, String)>,
+    _guard: TerminalGuard,
+}
+
+impl Renderer for TuiFrontend {
+    fn draw(&mut self, state: &mut State, status: &str) -> Result<(), TetrErr> {
+        let flash = self.flash.rows(state);
+        let grid = build_grid(state, self.previews, &flash, self.hint);
+
+        let mut out = Vec::new();
+        queue!(
+            out,
+            MoveTo(0, 0),
+            Print(status),
+            Clear(ClearType::UntilNewLine)
+        )?;
+        for (y, row) in grid.cells.iter().enumerate() {
+            queue!(out, MoveTo(0, y as u16 + 1))?;
+            for cell in row {
+                match cell {
+                    Some((r, g, b)) => queue!(
+                        out,
+                        SetBackgroundColor(Color::Rgb {
+                            r: *r,
+                            g: *g,
+                            b: *b
+                        }),
+                        Print("  ")
+                    )?,
+                    None => queue!(out, ResetColor, Print("  "))?,
+                }
+            }
+            queue!(out, ResetColor, Clear(ClearType::UntilNewLine))?;
+        }
+        // Stats go right of the grid, from its second row
+        for (i, (label, value)) in self.stats.iter().enumerate() {
+            queue!(
+                out,
+                MoveTo(grid.width as u16 * 2 + 2, i as u16 + 2),
+                Print(format!("{:<8}{}", label, value)),
+                Clear(ClearType::UntilNewLine)
+            )?;
+        }
+        if let Some(msg) = self.messages.last() {
+            queue!(out, MoveTo(0, grid.cells.len() as u16 + 1), Print(msg))?;
+        }
+        queue!(out, Clear(ClearType::FromCursorDown))?;
+
+        if out != self.last {
+            let mut stdout = stdout();
+            stdout.write_all(&out)?;
+            stdout.flush()?;
+            self.last = out;
+        }
+        Ok(())
+    }
+
+    fn event(&mut self, event: &GameEvent) {
+        self.flash.event(event);
+    }
+
+    fn hint(&mut self, piece: Option<Piece>) {
+        self.hint = piece;
+    }
+
+    fn stats(&mut self, stats: &[(&'static str, String)]) {
+        self.stats = stats.to_vec();
+    }
+}
+
+impl TuiFrontend {
+    fn key(&self, event: KeyEvent) -> Vec<Input> {
+        let code = match event.code {
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                return vec![Input::Quit]
+            }
+            KeyCode::Esc => return vec![Input::Quit],
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            code => code,
+        };
+        let action = match self.keys.action(code) {
+            Some(action) => action,
+            None => return vec![],
+        };
+        match (event.kind, self.releases) {
+            (KeyEventKind::Press, true) => vec![Input::Action(action, true)],
+            (KeyEventKind::Release, _) => vec![Input::Action(action, false)],
+            (KeyEventKind::Press, false) => {
+                vec![Input::Action(action, true), Input::Action(action, false)]
+            }
+            (KeyEventKind::Repeat, _) => vec![],
+        }
+    }
+}
+
+impl Frontend for TuiFrontend {
+    // Terminals don't timestamp input, it's as precise as how often this is
+    // called
+    fn poll(&mut self) -> Result<Vec<(Instant, Input)>, TetrErr> {
+        let mut inputs = Vec::new();
+        while event::poll(Duration::ZERO)? {
+            let time = Instant::now();
+            match event::read()? {
+                Event::Key(key) => inputs.extend(self.key(key).into_iter().map(|i| (time, i))),
+                // Everything has to be redrawn after a resize
+                Event::Resize(..) => self.last.clear(),
+                _ => (),
+            }
+        }
+        Ok(inputs)
+    }
+
+    fn binding_name(&self, action: Action) -> String {
+        match self.keys.bindings(action).first() {
+            Some(kc) => terminal_key_name(*kc),
+            None => "(unbound)".to_string(),
+        }
+    }
+
+    // Printing would garble the screen, messages are shown below the board
+    // and printed once the terminal is restored
+    fn message(&mut self, msg: &str) {
+        self.messages.push(msg.to_string());
+    }
+}
+
+/// Play in the terminal, or watch `replay` if given
+pub fn run_terminal(replay: Option<&Path>) -> Result<(), TetrErr> {
+    let playback = replay.map(Replay::load).transpose()?;
+    let (table, config) = load_config(playback.as_ref())?;
+    let keys = terminal_keys(config.keys())?;
+
+    let guard = TerminalGuard::new()?;
+    let mut frontend = TuiFrontend {
+        keys,
+        previews: config.previews(),
+        releases: guard.enhanced,
+        last: Vec::new(),
+        messages: Vec::new(),
+        flash: ClearFlash::default(),
+        hint: None,
+        stats: Vec::new(),
+        _guard: guard,
+    };
+
+    let result = game::run(&mut frontend, &table, &config, playback.as_ref());
+
+    let messages = std::mem::take(&mut frontend.messages);
+    drop(frontend);
+    for msg in messages {
+        println!("{}", msg);
+    }
+    result
+}